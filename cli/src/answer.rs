@@ -26,18 +26,20 @@ impl From<&str> for Answer {
 }
 
 impl From<Cow<'_, str>> for Answer {
-    // dry
+    // the payload is kept verbatim: data answers are multiline (status, export)
     fn from(value: Cow<'_, str>) -> Self {
-        let mut as_array: Vec<&str> = value.split_whitespace().collect();
-        let command = as_array.remove(0);
+        let value = value.trim_end();
+        let (command, payload) = value
+            .split_once(char::is_whitespace)
+            .unwrap_or((value, ""));
         match command {
-            "error" => Answer::Error(as_array.join(" ")),
-            "success" => Answer::Successfully(if as_array.is_empty() {
+            "error" => Answer::Error(payload.to_string()),
+            "success" => Answer::Successfully(if payload.trim().is_empty() {
                 None
             } else {
-                Some(as_array.join(" "))
+                Some(payload.to_string())
             }),
-            "data" => Answer::Data(as_array.join(" ")),
+            "data" => Answer::Data(payload.to_string()),
             _ => Answer::Invalid,
         }
     }
@@ -5,16 +5,24 @@ use tokio::{
     net::UnixStream,
 };
 
-use crate::{answer::Answer, SOCKET_PATH};
+use crate::{answer::Answer, config, remote};
 
 pub enum Commands {
     Start(String),
     // Remove(String),
     Stop(String),
     Status(Option<String>),
+    Describe(String),
+    Scale(String),
+    Run(String),
     Backup,
     Restart(String),
+    Reload(String),
     Delete(String),
+    Export,
+    Import(String),
+    Upgrade,
+    Audit(String),
     Load,
 }
 
@@ -23,24 +31,46 @@ impl Commands {
         let (command, message): (&str, String) = match self {
             Self::Start(message) => ("start", message.clone()),
             Self::Restart(message) => ("restart", message.clone()),
+            Self::Reload(message) => ("reload", message.clone()),
             Self::Stop(message) => ("stop", message.clone()),
             Self::Status(message) => ("status", message.clone().unwrap_or("".to_string())),
+            Self::Describe(message) => ("describe", message.clone()),
+            Self::Scale(message) => ("scale", message.clone()),
+            Self::Run(message) => ("run", message.clone()),
             Self::Backup => ("backup", "".to_string()),
             Self::Delete(message) => ("delete", message.clone()),
+            Self::Export => ("export", "".to_string()),
+            Self::Import(message) => ("import", message.clone()),
+            Self::Upgrade => ("upgrade", "".to_string()),
+            Self::Audit(message) => ("audit", message.clone()),
             Self::Load => ("message", "".to_string()),
         };
-        let mut stream = if let Ok(stream) = UnixStream::connect(SOCKET_PATH).await {
-            stream
-        } else {
-            eprintln!("Error: Can't connect UnixStream");
-            process::exit(1);
+        let request = format!("{} {}", command, message);
+        if let Some(context) = config::context() {
+            return match remote::send(&context, &request).await {
+                Ok(buf) => String::from_utf8_lossy(&buf).into(),
+                Err(err) => {
+                    eprintln!("Error: Can't reach {}: {}", context.host, err);
+                    process::exit(1);
+                }
+            };
+        }
+        let socket = config::socket_path();
+        let mut stream = match UnixStream::connect(&socket).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("Error: Can't connect to {}: {}", socket.display(), err);
+                process::exit(1);
+            }
         };
         stream
-            .write_all(&format!("{} {}", command, message).as_bytes())
+            .write_all(request.as_bytes())
             .await
             .unwrap();
-        let mut buf = [0; 1024];
-        let n = stream.read(&mut buf).await.unwrap();
-        String::from_utf8_lossy(&buf[..n]).into()
+        // The daemon reads until we close our side
+        stream.shutdown().await.unwrap();
+        let mut buf = vec![];
+        stream.read_to_end(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf).into()
     }
 }
@@ -5,13 +5,21 @@ use tokio::{
     net::UnixStream,
 };
 
-use crate::{answer::Answer, SOCKET_PATH};
+use crate::answer::Answer;
 
+pub mod audit;
 pub mod backup;
+pub mod daemon;
 pub mod delete;
+pub mod describe;
 pub mod ecosystem;
+pub mod export;
 pub mod help;
+pub mod import;
+pub mod reload;
 pub mod restart;
+pub mod run;
+pub mod scale;
 pub mod start;
 pub mod status;
 pub mod stop;
@@ -20,6 +28,9 @@ pub struct Arguments {
     pub positional: Vec<String>,
     pub flags: Vec<char>,
     pub options: Vec<String>,
+    /// Everything after `--`, taken as is
+    pub rest: Vec<String>,
+    raw: Vec<String>,
 }
 
 impl Arguments {
@@ -28,8 +39,32 @@ impl Arguments {
             positional: vec![],
             flags: vec![],
             options: vec![],
+            rest: vec![],
+            raw: vec![],
         }
     }
+
+    /// The value of `--option value` or `--option=value`
+    pub fn value(&self, option: &str) -> Option<String> {
+        self.values(option).into_iter().next()
+    }
+
+    /// Every value given to an option that can be repeated
+    pub fn values(&self, option: &str) -> Vec<String> {
+        let option = format!("--{}", option);
+        let mut values = vec![];
+        let mut raw = self.raw.iter();
+        while let Some(arg) = raw.next() {
+            if *arg == option {
+                values.extend(raw.next().cloned());
+            } else if let Some(value) =
+                arg.strip_prefix(&option).and_then(|arg| arg.strip_prefix('='))
+            {
+                values.push(value.to_string());
+            }
+        }
+        values
+    }
 }
 
 pub async fn handle_command(args: Vec<String>) {
@@ -39,6 +74,11 @@ pub async fn handle_command(args: Vec<String>) {
     }
     let command = args.remove(0);
     let mut arguments = Arguments::default();
+    if let Some(separator) = args.iter().position(|arg| arg == "--") {
+        arguments.rest = args.split_off(separator + 1);
+        args.pop();
+    }
+    arguments.raw = args.clone();
     for arg in args {
         if arg.starts_with("-") {
             if arg.starts_with("--") {
@@ -62,9 +102,17 @@ pub async fn handle_command(args: Vec<String>) {
         "start" | "letsgo" => start::exec(arguments).await,
         "stop" | "pause" => stop::exec(arguments).await,
         "backup" | "save" => backup::exec(arguments).await,
-        "restart" | "reload" => restart::exec(arguments).await,
+        "restart" => restart::exec(arguments).await,
+        "reload" => reload::exec(arguments).await,
         "status" => status::exec(arguments).await,
+        "describe" | "info" => describe::exec(arguments).await,
         "delete" => delete::exec(arguments).await,
+        "scale" => scale::exec(arguments).await,
+        "run" => run::exec(arguments).await,
+        "export" | "dump" => export::exec(arguments).await,
+        "import" => import::exec(arguments).await,
+        "daemon" => daemon::exec(arguments).await,
+        "audit" => audit::exec(arguments).await,
         "help" => help::exec(),
         _ => help::exec(),
     }
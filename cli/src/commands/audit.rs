@@ -0,0 +1,6 @@
+use crate::{command::Commands, commands::Arguments};
+
+pub async fn exec(args: Arguments) {
+    let since = args.value("since").unwrap_or_default();
+    println!("{}", Commands::Audit(since).send().await);
+}
@@ -0,0 +1,8 @@
+use crate::{command::Commands, commands::Arguments};
+
+pub async fn exec(args: Arguments) {
+    match args.positional.first().map(|arg| arg.as_str()) {
+        Some("upgrade") => println!("{}", Commands::Upgrade.send().await),
+        _ => println!("Bad usage: fpm daemon upgrade"),
+    }
+}
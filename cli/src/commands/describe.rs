@@ -0,0 +1,9 @@
+use crate::{command::Commands, commands::Arguments};
+
+pub async fn exec(args: Arguments) {
+    if let Some(arg) = args.positional.first() {
+        println!("{}", Commands::Describe(arg.clone()).send().await);
+        return;
+    }
+    println!("Bad usage: fpm describe <id / title>");
+}
@@ -1,17 +1,32 @@
 use std::{fmt::Display, io::stdin, process};
 
 use dialoguer::{console::Color, theme::ColorfulTheme, Input, Select};
+use serde::Serialize;
 use tokio::fs;
 
-#[derive(Clone, Debug)]
+/// A command line run by the shell, or an argv the daemon executes without one
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum Start {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct Ecosystem {
     pub title: String,
-    pub start: String,
-    pub shell: String,
+    pub start: Start,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub restart: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub logs: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub logs_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub watch: Option<Vec<String>>,
 }
 
@@ -19,8 +34,8 @@ impl Default for Ecosystem {
     fn default() -> Self {
         Self {
             title: String::from(""),
-            start: String::from(""),
-            shell: String::from(""),
+            start: Start::Shell(String::from("")),
+            shell: None,
             description: None,
             restart: None,
             logs: None,
@@ -34,46 +49,42 @@ impl Display for Ecosystem {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         const QUOTES: &str = "\"";
         const QUOTES_REPLACE: &str = "\\\"";
-        let mut result = format!(
-            "title=\"{}\"\nstart=\"{}\"\nshell=\"{}\"",
-            self.title.replace(QUOTES, QUOTES_REPLACE),
-            self.start.replace(QUOTES, QUOTES_REPLACE),
-            self.shell.replace(QUOTES, QUOTES_REPLACE)
-        );
+        const BACKSLASH: &str = "\\";
+        const BACKSLASH_REPLACE: &str = "\\\\";
+        let escape = |value: &str| {
+            value
+                .replace(BACKSLASH, BACKSLASH_REPLACE)
+                .replace(QUOTES, QUOTES_REPLACE)
+        };
+        let array = |values: &[String]| {
+            let values: Vec<String> = values
+                .iter()
+                .map(|value| format!("\"{}\"", escape(value)))
+                .collect();
+            format!("[{}]", values.join(", "))
+        };
+        let start = match &self.start {
+            Start::Shell(line) => format!("\"{}\"", escape(line)),
+            Start::Argv(argv) => array(argv),
+        };
+        let mut result = format!("title=\"{}\"\nstart={}", escape(&self.title), start);
+        if let Some(shell) = self.shell.clone() {
+            result = format!("{result}\nshell=\"{}\"", escape(&shell));
+        };
         if let Some(description) = self.description.clone() {
-            result = format!(
-                "{result}\ndescription=\"{}\"",
-                description.replace(QUOTES, QUOTES_REPLACE)
-            );
+            result = format!("{result}\ndescription=\"{}\"", escape(&description));
         };
         if let Some(restart) = self.restart.clone() {
-            result = format!(
-                "{result}\nrestart=\"{}\"",
-                restart.replace(QUOTES, QUOTES_REPLACE)
-            );
+            result = format!("{result}\nrestart=\"{}\"", escape(&restart));
         };
         if let Some(logs) = self.logs.clone() {
-            result = format!(
-                "{result}\nlogs=\"{}\"",
-                logs.replace(QUOTES, QUOTES_REPLACE)
-            );
+            result = format!("{result}\nlogs=\"{}\"", escape(&logs));
         };
         if let Some(logs_mode) = self.logs_mode.clone() {
-            result = format!(
-                "{result}\nlogs_mode=\"{}\"",
-                logs_mode.replace(QUOTES, QUOTES_REPLACE)
-            );
+            result = format!("{result}\nlogs_mode=\"{}\"", escape(&logs_mode));
         };
         if let Some(watch) = self.watch.clone() {
-            result = format!("{result}\nwatch=[",);
-            for (index, el) in watch.iter().enumerate() {
-                if index == 0 {
-                    result = format!("{result}\"{}\"", el.replace(QUOTES, QUOTES_REPLACE));
-                } else {
-                    result = format!("{result}, \"{}\"", el.replace(QUOTES, QUOTES_REPLACE));
-                }
-            }
-            result = format!("{result}]")
+            result = format!("{result}\nwatch={}", array(&watch));
         };
         write!(f, "{result}")
     }
@@ -85,8 +96,8 @@ pub async fn exec(args: Arguments) {
     if args.flags.contains(&'q') {
         let default_ecosystem = Ecosystem {
             title: "your project".to_string(),
-            start: "echo \"hello shell!\" && ./your_app".to_string(),
-            shell: "/bin/sh".to_string(),
+            start: Start::Shell("echo \"hello shell!\" && ./your_app".to_string()),
+            shell: Some("/bin/sh".to_string()),
             description: Some("Your cool project to launch in fpm".to_string()),
             restart: Some("always".to_string()),
             logs: Some("fpm-log.txt".to_string()),
@@ -121,12 +132,13 @@ async fn setup_file() {
         .with_prompt("Command to start?")
         .interact_text()
         .unwrap();
-    ecosystem.start = command;
+    ecosystem.start = Start::Shell(command);
     let shell: String = Input::with_theme(&ColorfulTheme::default())
         .with_prompt("Shell path?")
+        .default("/bin/sh".to_string())
         .interact_text()
         .unwrap();
-    ecosystem.shell = shell;
+    ecosystem.shell = Some(shell);
     let options = vec!["always", "never", "on error"];
     let restart = Select::with_theme(&ColorfulTheme::default())
         .with_prompt("When restart?")
@@ -0,0 +1,12 @@
+use crate::{answer::Answer, command::Commands, commands::Arguments};
+use std::process;
+
+pub async fn exec(_args: Arguments) {
+    match Commands::Export.send().await {
+        Answer::Data(dump) => println!("{}", dump),
+        answer => {
+            eprintln!("{}", answer);
+            process::exit(1);
+        }
+    }
+}
@@ -2,15 +2,25 @@ pub fn exec() {
     println!("feproldo's process manager");
     println!("Usage:");
     println!("\tfpm <command> [...]");
+    println!("\tfpm --host <context> <command> [...] - talks to a remote daemon, contexts are set in config.json");
     println!("");
     println!("Commands:");
     println!("\thelp - shows this text");
-    println!("\tstart [id|title] - starts a new process or resumes a stopped one");
+    println!("\tstart [id|title] - starts a new process or resumes a stopped one, runs a job right away");
+    println!("\tstart [--name N] [--restart R] [--watch P] [--logs F] [--save] -- <command...> - starts a process without an ecosystem file, --save writes one");
     println!("\tstop <id|title> - stops the process");
     println!("\tstatus - displays information about running processes");
+    println!("\tdescribe <id|title> - shows uptime, restarts, last exit and recent state changes of a process");
     println!("\trestart <id|title> - reads the ecosystem and restarts the process with it");
+    println!("\treload <id|title> - like restart, but each instance is replaced only once its successor is ready");
     println!("\tecosystem [-q] - creates an ecosystem file (configuration file)");
     println!("\tbackup - saves current processes for restarting them in the future");
     println!("\tdelete - stops and removes the process from the list");
+    println!("\trun --name <name> [--timeout S] [--retries N] -- <command...> - queues a one-off task that isn't restarted once it succeeds");
+    println!("\tscale <title> <N> - runs N instances of the process, addressed as title:0, title:1...");
+    println!("\texport - prints every process with its ecosystem, e.g. fpm export > dump.toml");
+    println!("\timport <file> [--path-prefix-map old=new...] - recreates processes from an export");
+    println!("\taudit [--since T] - shows who changed what, T is a time ago like 30m, 12h, 7d or a date like \"2025-01-31 03:00\"");
+    println!("\tdaemon upgrade - re-executes the daemon from its updated binary without stopping processes");
 }
 
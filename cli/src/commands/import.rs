@@ -0,0 +1,44 @@
+use crate::{command::Commands, commands::Arguments};
+use serde_json::json;
+use std::{fs, process};
+
+const PREFIX_MAP_OPTION: &str = "path-prefix-map";
+
+pub async fn exec(args: Arguments) {
+    let file = if let Some(file) = args.positional.first() {
+        file
+    } else {
+        println!("Bad usage: fpm import <file> [--path-prefix-map old=new...]");
+        return;
+    };
+    // The daemon reads the file itself, so it needs a path that doesn't depend on our cwd
+    let file = match fs::canonicalize(file) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("Error: Can't open {}: {}", file, err);
+            process::exit(1);
+        }
+    };
+
+    // Both "--path-prefix-map old=new" and "--path-prefix-map=old=new" are accepted
+    let mut mappings: Vec<String> = args
+        .options
+        .iter()
+        .filter_map(|option| option.strip_prefix(PREFIX_MAP_OPTION)?.strip_prefix('='))
+        .map(|mapping| mapping.to_string())
+        .collect();
+    if args.options.iter().any(|option| option == PREFIX_MAP_OPTION) {
+        mappings.extend(args.positional.iter().skip(1).cloned());
+    }
+    if let Some(mapping) = mappings.iter().find(|mapping| !mapping.contains('=')) {
+        eprintln!("Error: Invalid path prefix mapping \"{}\", expected old=new", mapping);
+        process::exit(1);
+    }
+
+    // As JSON, so paths with spaces arrive intact
+    let request = json!({
+        "path": file,
+        "path_prefix_map": mappings,
+    });
+    println!("{}", Commands::Import(request.to_string()).send().await);
+}
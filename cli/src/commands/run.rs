@@ -0,0 +1,43 @@
+use std::{env, process};
+
+use serde_json::json;
+
+use crate::{command::Commands, commands::Arguments};
+
+pub async fn exec(args: Arguments) {
+    let (Some(name), false) = (args.value("name"), args.rest.is_empty()) else {
+        println!("Bad usage: fpm run --name <name> [--timeout S] [--retries N] -- <command...>");
+        return;
+    };
+    let timeout = match args.value("timeout").map(|timeout| timeout.parse::<u64>()) {
+        Some(Ok(timeout)) => Some(timeout),
+        Some(Err(_)) => {
+            println!("Bad usage: the timeout must be a number of seconds");
+            return;
+        }
+        None => None,
+    };
+    let retries = match args.value("retries").map(|retries| retries.parse::<u32>()) {
+        Some(Ok(retries)) => Some(retries),
+        Some(Err(_)) => {
+            println!("Bad usage: the number of retries must be a number");
+            return;
+        }
+        None => None,
+    };
+    let path = match env::current_dir() {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("Error: Can't get the current directory: {}", err);
+            process::exit(1);
+        }
+    };
+    let request = json!({
+        "name": name,
+        "path": path,
+        "command": args.rest,
+        "timeout": timeout,
+        "retries": retries,
+    });
+    println!("{}", Commands::Run(request.to_string()).send().await);
+}
@@ -0,0 +1,16 @@
+use crate::{command::Commands, commands::Arguments};
+
+pub async fn exec(args: Arguments) {
+    if let (Some(title), Some(instances)) = (args.positional.first(), args.positional.get(1)) {
+        if instances.parse::<u32>().is_err() {
+            println!("Bad usage: the number of instances must be a number");
+            return;
+        }
+        println!(
+            "{}",
+            Commands::Scale(format!("{} {}", title, instances)).send().await
+        );
+        return;
+    }
+    println!("Bad usage: fpm scale <title> <instances>");
+}
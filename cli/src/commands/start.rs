@@ -1,7 +1,20 @@
-use crate::{command::Commands, commands::Arguments, ECOSYSTEM_NAME};
-use std::process;
+use crate::{
+    command::Commands,
+    commands::{
+        ecosystem::{Ecosystem, Start},
+        Arguments,
+    },
+    ECOSYSTEM_NAME,
+};
+use serde_json::json;
+use std::{path::Path, process};
+
+const RESTART_MODES: [&str; 3] = ["always", "never", "on_error"];
 
 pub async fn exec(args: Arguments) {
+    if !args.rest.is_empty() {
+        return start_adhoc(args).await;
+    }
     if args.positional.len() > 0 {
         if let Some(arg) = args.positional.get(0) {
             let answer = Commands::Start(arg.clone()).send().await;
@@ -19,3 +32,71 @@ pub async fn exec(args: Arguments) {
         .await;
     println!("{}", answer);
 }
+
+/// `fpm start [--name N] [--restart R] [--watch P...] [--logs F] [--save] -- cmd...`
+/// starts a process straight from its arguments. With `--save` they are written to an
+/// ecosystem file first, and the process is started from that file.
+async fn start_adhoc(args: Arguments) {
+    let name = match args.value("name") {
+        Some(name) => name,
+        None => match Path::new(&args.rest[0]).file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => args.rest[0].clone(),
+        },
+    };
+    let restart = args.value("restart");
+    if let Some(restart) = restart.as_deref() {
+        if !RESTART_MODES.contains(&restart) {
+            eprintln!(
+                "Error: Unknown restart mode \"{}\", expected one of {}",
+                restart,
+                RESTART_MODES.join(", ")
+            );
+            process::exit(1);
+        }
+    }
+    let watch: Vec<String> = args
+        .values("watch")
+        .iter()
+        .flat_map(|watch| watch.split(','))
+        .map(|watch| watch.to_string())
+        .collect();
+    let logs = args.value("logs");
+    let ecosystem = Ecosystem {
+        title: name,
+        start: Start::Argv(args.rest.clone()),
+        restart,
+        logs_mode: logs.as_ref().map(|_| "append".to_string()),
+        logs,
+        watch: if watch.is_empty() { None } else { Some(watch) },
+        ..Ecosystem::default()
+    };
+
+    let current_dir = std::env::current_dir().expect("Can't get current dir.");
+    if args.options.iter().any(|option| option == "save") {
+        let file = current_dir.join(ECOSYSTEM_NAME);
+        if file.exists() {
+            eprintln!(
+                "Error: {} already exists, not overwriting it",
+                file.display()
+            );
+            process::exit(1);
+        }
+        if let Err(err) = std::fs::write(&file, format!("{}\n", ecosystem)) {
+            eprintln!("Error: Can't create {}: {}", file.display(), err);
+            process::exit(1);
+        }
+        println!("Saved to {}", file.display());
+        let answer = Commands::Start(current_dir.to_string_lossy().to_string())
+            .send()
+            .await;
+        println!("{}", answer);
+        return;
+    }
+
+    let request = json!({
+        "path": current_dir,
+        "ecosystem": ecosystem,
+    });
+    println!("{}", Commands::Start(request.to_string()).send().await);
+}
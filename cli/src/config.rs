@@ -1,5 +1,9 @@
-use std::{env, fs, path::PathBuf, process};
+use std::{env, fs, os::unix::fs::MetadataExt, path::PathBuf, process, sync::OnceLock};
 use directories::ProjectDirs;
+use serde::Deserialize;
+
+/// The context `--host` picked, if any.
+static HOST: OnceLock<String> = OnceLock::new();
 
 pub fn get_cfg() -> Result<serde_json::Value, serde_json::Error> {
     
@@ -34,4 +38,88 @@ pub fn set_cfg (data: serde_json::Value) {
             Err(err) => {eprintln!("{err}"); process::exit(1)}
         }
     }
-}
\ No newline at end of file
+}
+
+/// Where the daemon listens: `socket` from config.json, otherwise `$XDG_RUNTIME_DIR/fpm.sock`,
+/// otherwise `fpm.sock` in /run/fpm for root or /tmp/fpm-<uid>, like the daemon picks it.
+pub fn socket_path() -> PathBuf {
+    if let Some(path) = get_cfg()
+        .ok()
+        .and_then(|cfg| cfg.get("socket")?.as_str().map(PathBuf::from))
+    {
+        return path;
+    }
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("fpm.sock"),
+        _ => {
+            // /proc/self belongs to whoever we run as
+            let uid = fs::metadata("/proc/self").map(|meta| meta.uid()).unwrap_or(0);
+            let dir = match uid {
+                0 => PathBuf::from("/run/fpm"),
+                _ => PathBuf::from(format!("/tmp/fpm-{uid}")),
+            };
+            // Anyone could have created it in /tmp and put a socket of their own in it
+            if let Ok(meta) = fs::symlink_metadata(&dir) {
+                if !meta.is_dir() || ![uid, 0].contains(&meta.uid()) || meta.mode() & 0o022 != 0 {
+                    eprintln!(
+                        "Error: {} isn't a directory of yours, not connecting to the socket in it",
+                        dir.display()
+                    );
+                    process::exit(1);
+                }
+            }
+            dir.join("fpm.sock")
+        }
+    }
+}
+
+/// A daemon reached over TCP, from `contexts` in config.json:
+/// `{"contexts": {"box1": {"host": "box1:7070", "token": "...", "ca": "/path/ca.pem"}}}`
+#[derive(Deserialize, Debug, Clone)]
+pub struct Context {
+    /// Host and port of the daemon's TCP listener
+    pub host: String,
+    pub token: String,
+    /// PEM certificate of the CA that signed the daemon's certificate
+    pub ca: PathBuf,
+    /// The name in the certificate, the host of `host` by default
+    pub server_name: Option<String>,
+}
+
+/// Takes `--host <context>` or `--host=<context>` from before the command.
+pub fn take_host(args: &mut Vec<String>) {
+    let Some(first) = args.first() else {
+        return;
+    };
+    let host = if first == "--host" && args.len() > 1 {
+        let host = args.remove(1);
+        args.remove(0);
+        host
+    } else if let Some(host) = first.strip_prefix("--host=") {
+        let host = host.to_string();
+        args.remove(0);
+        host
+    } else {
+        return;
+    };
+    let _ = HOST.set(host);
+}
+
+/// The context chosen with `--host`, `None` for the local daemon.
+pub fn context() -> Option<Context> {
+    let name = HOST.get()?;
+    let context = get_cfg()
+        .ok()
+        .and_then(|cfg| cfg.get("contexts")?.get(name).cloned());
+    match context.map(serde_json::from_value::<Context>) {
+        Some(Ok(context)) => Some(context),
+        Some(Err(err)) => {
+            eprintln!("Error: Invalid context {} in config.json: {}", name, err);
+            process::exit(1);
+        }
+        None => {
+            eprintln!("Error: No context {} in config.json", name);
+            process::exit(1);
+        }
+    }
+}
@@ -3,15 +3,16 @@ mod answer;
 mod command;
 mod commands;
 mod config;
+mod remote;
 
 const ECOSYSTEM_NAME: &str = "pm-ecosystem.toml";
 const DOCUMENTATION_URL: &str = "https://fpm.feproldo.ru/";
-const SOCKET_PATH: &str = "/tmp/fpm.sock";
 
 #[tokio::main]
 async fn main() {
     let _ = config::get_cfg();
     let mut args: Vec<String> = env::args().collect();
     args.remove(0);
+    config::take_host(&mut args);
     commands::handle_command(args).await;
 }
@@ -0,0 +1,50 @@
+use std::{error::Error, sync::Arc};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    rustls::{
+        pki_types::{pem::PemObject, CertificateDer, ServerName},
+        ClientConfig, RootCertStore,
+    },
+    TlsConnector,
+};
+
+use crate::config::Context;
+
+/// Sends a request to a daemon's TCP listener. The daemon trusts the token, we trust the
+/// certificate only if `ca` signed it.
+pub async fn send(context: &Context, request: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(&context.ca)
+        .map_err(|err| format!("can't read {}: {err}", context.ca.display()))?
+    {
+        roots.add(cert?)?;
+    }
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let server_name = match &context.server_name {
+        Some(server_name) => server_name.clone(),
+        None => match context.host.rsplit_once(':') {
+            Some((host, _)) => host.trim_matches(['[', ']']).to_string(),
+            None => return Err(format!("{} has no port", context.host).into()),
+        },
+    };
+    let server_name = ServerName::try_from(server_name)?;
+
+    let stream = TcpStream::connect(&context.host).await?;
+    let mut stream = TlsConnector::from(Arc::new(config))
+        .connect(server_name, stream)
+        .await?;
+    stream
+        .write_all(format!("Bearer {}\n{}", context.token, request).as_bytes())
+        .await?;
+    // Tells the daemon the request is complete
+    stream.shutdown().await?;
+    let mut buf = vec![];
+    stream.read_to_end(&mut buf).await?;
+    Ok(buf)
+}
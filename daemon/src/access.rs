@@ -0,0 +1,243 @@
+use std::{collections::HashMap, ffi::CString, fmt::Display, net::SocketAddr, sync::OnceLock};
+
+use nix::unistd::{Gid, Group, Uid, User, geteuid, getgrouplist};
+use serde::Deserialize;
+use tokio::net::unix::UCred;
+
+use crate::config::Config;
+
+/// Applies to every command in `access`.
+const ANY_COMMAND: &str = "*";
+/// Commands that only look, everything else changes something.
+const READ_ONLY: [&str; 5] = ["status", "describe", "export", "audit", "logs"];
+
+pub fn is_read_only(command: &str) -> bool {
+    READ_ONLY.contains(&command)
+}
+
+/// What a remote token may do.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Only the commands that don't change anything
+    #[serde(rename = "read")]
+    Read,
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+/// Who sent a command.
+#[derive(Debug, Clone)]
+pub enum Peer {
+    /// A local user on the socket, known by its credentials
+    Local(UCred),
+    /// A client of the TCP listener, known by the name of its token
+    Remote {
+        addr: SocketAddr,
+        token: String,
+        scope: Scope,
+    },
+}
+
+impl Display for Peer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Local(cred) => write!(f, "uid {}", cred.uid()),
+            Self::Remote { addr, token, .. } => write!(f, "token {token} from {addr}"),
+        }
+    }
+}
+
+/// Users and groups, by name or id, allowed to send a command.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Allow {
+    pub users: Vec<String>,
+    pub groups: Vec<String>,
+}
+
+/// `access` from the config with every name resolved to an id.
+#[derive(Debug, Default)]
+struct Access {
+    rules: HashMap<String, (Vec<Uid>, Vec<Gid>)>,
+}
+
+static ACCESS: OnceLock<Access> = OnceLock::new();
+
+fn uid(name: &str) -> Option<Uid> {
+    match name.parse::<u32>() {
+        Ok(uid) => Some(Uid::from_raw(uid)),
+        Err(_) => User::from_name(name).ok().flatten().map(|user| user.uid),
+    }
+}
+
+fn gid(name: &str) -> Option<Gid> {
+    match name.parse::<u32>() {
+        Ok(gid) => Some(Gid::from_raw(gid)),
+        Err(_) => Group::from_name(name).ok().flatten().map(|group| group.gid),
+    }
+}
+
+/// Resolves the allowlist once at startup. Names that don't exist are left out with a warning,
+/// they never match anyone.
+pub fn init(config: &Config) {
+    let mut access = Access::default();
+    for (command, allow) in &config.access {
+        let uids = allow
+            .users
+            .iter()
+            .filter_map(|name| {
+                let uid = uid(name);
+                if uid.is_none() {
+                    eprintln!("Unknown user \"{name}\" in access.{command}");
+                }
+                uid
+            })
+            .collect();
+        let gids = allow
+            .groups
+            .iter()
+            .filter_map(|name| {
+                let gid = gid(name);
+                if gid.is_none() {
+                    eprintln!("Unknown group \"{name}\" in access.{command}");
+                }
+                gid
+            })
+            .collect();
+        access.rules.insert(command.clone(), (uids, gids));
+    }
+    let _ = ACCESS.set(access);
+}
+
+/// Whether anyone but the daemon's own user may connect. Otherwise the socket is only
+/// accessible to its owner.
+pub fn is_shared() -> bool {
+    ACCESS.get().is_some_and(|access| !access.rules.is_empty())
+}
+
+/// The groups of the peer: the one it connected with and the ones its user is a member of.
+fn groups(peer: &UCred) -> Vec<Gid> {
+    let gid = Gid::from_raw(peer.gid());
+    let mut groups = User::from_uid(Uid::from_raw(peer.uid()))
+        .ok()
+        .flatten()
+        .and_then(|user| CString::new(user.name).ok())
+        .and_then(|name| getgrouplist(&name, gid).ok())
+        .unwrap_or_default();
+    groups.push(gid);
+    groups
+}
+
+/// Whether the peer may send `command`. Remote clients get what the scope of their token
+/// allows.
+pub fn allowed(peer: &Peer, command: &str) -> bool {
+    match peer {
+        Peer::Local(cred) => allowed_locally(cred, command),
+        Peer::Remote { scope, .. } => *scope == Scope::Admin || is_read_only(command),
+    }
+}
+
+/// The daemon's own user and root may send anything, anyone else has to be listed for the
+/// command or for `"*"`.
+fn allowed_locally(peer: &UCred, command: &str) -> bool {
+    let uid = Uid::from_raw(peer.uid());
+    if uid == geteuid() || uid.is_root() {
+        return true;
+    }
+    ACCESS
+        .get()
+        .is_some_and(|access| access.allows(uid, || groups(peer), command))
+}
+
+impl Access {
+    /// Whether `uid` or one of its `groups` is listed for `command` or for `"*"`. The groups
+    /// are only looked up when the uid isn't listed.
+    fn allows(&self, uid: Uid, groups: impl FnOnce() -> Vec<Gid>, command: &str) -> bool {
+        let rules: Vec<&(Vec<Uid>, Vec<Gid>)> = [command, ANY_COMMAND]
+            .into_iter()
+            .filter_map(|command| self.rules.get(command))
+            .collect();
+        if rules.iter().any(|(uids, _)| uids.contains(&uid)) {
+            return true;
+        }
+        let groups = groups();
+        rules
+            .iter()
+            .any(|(_, gids)| gids.iter().any(|gid| groups.contains(gid)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(rules: &[(&str, &[u32], &[u32])]) -> Access {
+        let rules = rules
+            .iter()
+            .map(|(command, uids, gids)| {
+                let uids = uids.iter().copied().map(Uid::from_raw).collect();
+                let gids = gids.iter().copied().map(Gid::from_raw).collect();
+                (command.to_string(), (uids, gids))
+            })
+            .collect();
+        Access { rules }
+    }
+
+    fn groups(gids: &[u32]) -> impl FnOnce() -> Vec<Gid> {
+        let gids: Vec<Gid> = gids.iter().copied().map(Gid::from_raw).collect();
+        move || gids
+    }
+
+    #[test]
+    fn matches_users_per_command_and_for_any() {
+        let access = access(&[("status", &[1000], &[]), ("*", &[1001], &[])]);
+        let (alice, bob, eve) = (
+            Uid::from_raw(1000),
+            Uid::from_raw(1001),
+            Uid::from_raw(1002),
+        );
+        assert!(access.allows(alice, groups(&[]), "status"));
+        assert!(!access.allows(alice, groups(&[]), "stop"));
+        assert!(access.allows(bob, groups(&[]), "stop"));
+        assert!(access.allows(bob, groups(&[]), "status"));
+        assert!(!access.allows(eve, groups(&[]), "status"));
+    }
+
+    #[test]
+    fn matches_any_of_the_groups() {
+        let access = access(&[("describe", &[], &[50]), ("*", &[], &[60])]);
+        let user = Uid::from_raw(1000);
+        assert!(access.allows(user, groups(&[10, 50]), "describe"));
+        assert!(!access.allows(user, groups(&[10, 50]), "stop"));
+        assert!(access.allows(user, groups(&[60]), "stop"));
+        assert!(!access.allows(user, groups(&[10]), "describe"));
+    }
+
+    #[test]
+    fn looks_groups_up_only_when_needed() {
+        let access = access(&[("status", &[1000], &[50])]);
+        let unused = || -> Vec<Gid> { panic!("the uid is listed") };
+        assert!(access.allows(Uid::from_raw(1000), unused, "status"));
+        assert!(!Access::default().allows(Uid::from_raw(1000), groups(&[50]), "status"));
+    }
+
+    #[test]
+    fn remote_peers_follow_their_scope() {
+        let peer = |scope| Peer::Remote {
+            addr: "127.0.0.1:7070".parse().unwrap(),
+            token: "ci".to_string(),
+            scope,
+        };
+        assert!(allowed(&peer(Scope::Read), "status"));
+        assert!(!allowed(&peer(Scope::Read), "stop"));
+        assert!(allowed(&peer(Scope::Admin), "stop"));
+    }
+
+    #[test]
+    fn resolves_ids_and_names() {
+        assert_eq!(uid("1234"), Some(Uid::from_raw(1234)));
+        assert_eq!(uid("root"), Some(Uid::from_raw(0)));
+        assert_eq!(gid("4321"), Some(Gid::from_raw(4321)));
+        assert_eq!(uid("no-such-user-here"), None);
+    }
+}
@@ -0,0 +1,506 @@
+use std::{
+    convert::Infallible, error::Error, fs, io::SeekFrom, net::SocketAddr,
+    os::unix::fs::PermissionsExt, path::PathBuf, sync::Arc, time::Duration,
+};
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full, Limited, channel::Channel, combinators::BoxBody};
+use hyper::{
+    Method, Request, Response, StatusCode, body::Incoming, header, server::conn::http1,
+    service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt},
+    net::{TcpListener, UnixListener},
+    sync::Mutex,
+};
+
+use crate::{
+    AppState,
+    access::{self, Peer},
+    metrics,
+    processes::Process,
+    remote::{self, Token},
+    socket,
+};
+
+/// Requests with a body only carry a path, nothing needs more than this.
+const MAX_BODY: usize = 64 * 1024;
+/// How many lines of a log are sent before following it, unless `?lines=` says otherwise.
+const DEFAULT_TAIL: usize = 50;
+/// How far back from the end of a log the last lines are looked for.
+const TAIL_WINDOW: u64 = 256 * 1024;
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+/// A comment sent when a log is quiet, it's how a gone client is noticed.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+type Body = BoxBody<Bytes, Infallible>;
+
+/// The HTTP/JSON API for tools and dashboards, with Prometheus metrics at `/metrics`. On a
+/// port it takes bearer tokens like the TCP listener, on a socket local users are checked
+/// like on the main one.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Api {
+    /// Loopback address and port to listen on, like `"127.0.0.1:7071"`. The API has no TLS,
+    /// anything further away goes through a proxy that adds it
+    pub listen: Option<String>,
+    /// Unix socket to listen on
+    pub socket: Option<PathBuf>,
+    /// Sent in plain text, so they must not be the tokens of `tcp`
+    #[serde(default)]
+    pub tokens: Vec<Token>,
+}
+
+pub async fn start_api(app_state: Arc<Mutex<AppState>>, api: Api) -> Result<(), Box<dyn Error>> {
+    if api.listen.is_none() && api.socket.is_none() {
+        return Err("neither listen nor socket is set".into());
+    }
+    if let Some(path) = api.socket.clone() {
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        let mode = if access::is_shared() { 0o666 } else { 0o600 };
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+        println!("API listening on {}...", path.display());
+        let app_state = app_state.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    continue;
+                };
+                let Ok(cred) = stream.peer_cred() else {
+                    continue;
+                };
+                let app_state = app_state.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |request| {
+                        handle(app_state.clone(), Some(Peer::Local(cred)), request)
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+    }
+    if let Some(listen) = api.listen {
+        let tokens = Arc::new(remote::usable_tokens(api.tokens)?);
+        let listener = TcpListener::bind(&listen).await?;
+        // Tokens would cross the network in plain text
+        if !listener.local_addr()?.ip().is_loopback() {
+            return Err(format!(
+                "{listen} isn't a loopback address, the API has no TLS, put a proxy in front of it"
+            )
+            .into());
+        }
+        println!("API listening on {}...", listen);
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let app_state = app_state.clone();
+            let tokens = tokens.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request: Request<Incoming>| {
+                    let peer = authenticate(&tokens, &request, addr);
+                    handle(app_state.clone(), peer, request)
+                });
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    }
+    Ok(())
+}
+
+fn authenticate(tokens: &[Token], request: &Request<Incoming>, addr: SocketAddr) -> Option<Peer> {
+    let header = request
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?;
+    let token = remote::authenticate(tokens, header)?;
+    Some(Peer::Remote {
+        addr,
+        token: token.name.clone(),
+        scope: token.scope,
+    })
+}
+
+fn reply(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(body.to_string())).boxed())
+        .unwrap_or_default()
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response<Body> {
+    reply(status, json!({ "ok": false, "error": message.into() }))
+}
+
+async fn handle(
+    app_state: Arc<Mutex<AppState>>,
+    peer: Option<Peer>,
+    request: Request<Incoming>,
+) -> Result<Response<Body>, Infallible> {
+    let Some(peer) = peer else {
+        return Ok(error(StatusCode::UNAUTHORIZED, "Invalid token"));
+    };
+    let path: Vec<String> = request
+        .uri()
+        .path()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(decode)
+        .collect();
+    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+    let method = request.method().clone();
+
+    let response = match (&method, path.as_slice()) {
+        (&Method::GET, ["processes"]) => match authorize(&peer, "status") {
+            Some(denied) => denied,
+            None => list(app_state).await,
+        },
+        (&Method::GET, ["metrics"]) => match authorize(&peer, "status") {
+            Some(denied) => denied,
+            None => Response::builder()
+                .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                .body(Full::new(Bytes::from(metrics::render(app_state).await)).boxed())
+                .unwrap_or_default(),
+        },
+        (&Method::POST, ["processes"]) => start_path(app_state, &peer, request).await,
+        (&Method::GET, ["processes", target]) => match authorize(&peer, "describe") {
+            Some(denied) => denied,
+            None => describe(app_state, target).await,
+        },
+        (&Method::GET, ["processes", target, "logs"]) => match authorize(&peer, "logs") {
+            Some(denied) => denied,
+            None => logs(app_state, target, request.uri().query()).await,
+        },
+        (
+            &Method::POST,
+            [
+                "processes",
+                target,
+                action @ ("start" | "stop" | "restart" | "reload"),
+            ],
+        ) => command(app_state, &peer, action, target).await,
+        (&Method::DELETE, ["processes", target]) => {
+            command(app_state, &peer, "delete", target).await
+        }
+        (_, ["processes", ..]) => error(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed"),
+        _ => error(StatusCode::NOT_FOUND, "Not found"),
+    };
+    Ok(response)
+}
+
+/// A forbidden response if the peer may not use `command`.
+fn authorize(peer: &Peer, command: &str) -> Option<Response<Body>> {
+    if access::allowed(peer, command) {
+        return None;
+    }
+    Some(error(
+        StatusCode::FORBIDDEN,
+        format!("{} isn't allowed to {}", peer, command),
+    ))
+}
+
+/// Undoes the percent-encoding of a path segment, `api%3A1` is `api:1`.
+fn decode(segment: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok());
+        match (byte, hex.and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+#[derive(Serialize)]
+struct ProcessInfo {
+    id: u32,
+    name: String,
+    title: String,
+    instance: u32,
+    status: String,
+    pid: Option<u32>,
+    /// Seconds
+    uptime: Option<i64>,
+    restarts: u32,
+    last_exit: Option<String>,
+    last_error: Option<String>,
+    descendants: usize,
+}
+
+#[derive(Serialize)]
+struct ProcessDetails {
+    #[serde(flatten)]
+    info: ProcessInfo,
+    path: String,
+    start: String,
+    description: Option<String>,
+    logs: Option<PathBuf>,
+    /// State changes, oldest first
+    history: Vec<HistoryEntry>,
+}
+
+#[derive(Serialize)]
+struct HistoryEntry {
+    time: String,
+    status: String,
+}
+
+async fn info(process: &Process) -> ProcessInfo {
+    let status = process.status();
+    let state = process.state.lock().await;
+    ProcessInfo {
+        id: process.id,
+        name: process.name(),
+        title: process.ecosystem.title.clone(),
+        instance: process.instance,
+        status: status.to_string(),
+        pid: state.uid,
+        uptime: state.uptime(status).map(|uptime| uptime.num_seconds()),
+        restarts: state.restarts,
+        last_exit: state.last_exit.map(|exit| exit.to_string()),
+        last_error: state.last_error.clone(),
+        descendants: state.descendants.len(),
+    }
+}
+
+async fn sorted(app_state: &Arc<Mutex<AppState>>) -> Vec<Arc<Process>> {
+    let mut processes: Vec<Arc<Process>> =
+        app_state.lock().await.processes.values().cloned().collect();
+    processes.sort_by_key(|process| process.id);
+    processes
+}
+
+async fn list(app_state: Arc<Mutex<AppState>>) -> Response<Body> {
+    let mut processes = vec![];
+    for process in sorted(&app_state).await {
+        processes.push(info(&process).await);
+    }
+    reply(StatusCode::OK, json!(processes))
+}
+
+/// Every process `target` names, all instances of a title. Answers like `describe` does.
+async fn describe(app_state: Arc<Mutex<AppState>>, target: &str) -> Response<Body> {
+    let processes = app_state.lock().await.find_processes(target);
+    if processes.is_empty() {
+        return error(StatusCode::NOT_FOUND, "Couldn't find the process");
+    }
+    let mut details = vec![];
+    for process in processes {
+        let history = process
+            .state
+            .lock()
+            .await
+            .history
+            .iter()
+            .map(|(time, status)| HistoryEntry {
+                time: time.to_rfc3339(),
+                status: status.to_string(),
+            })
+            .collect();
+        details.push(ProcessDetails {
+            info: info(&process).await,
+            path: process.path.clone(),
+            start: process.ecosystem.start.to_string(),
+            description: process.ecosystem.description.clone(),
+            logs: process.log_path(),
+            history,
+        });
+    }
+    reply(StatusCode::OK, json!(details))
+}
+
+/// Runs a command the way the socket does, so it is authorized and audited the same.
+async fn command(
+    app_state: Arc<Mutex<AppState>>,
+    peer: &Peer,
+    command: &str,
+    target: &str,
+) -> Response<Body> {
+    if let Some(denied) = authorize(peer, command) {
+        return denied;
+    }
+    // It becomes part of a socket command, whitespace would smuggle in more arguments
+    if target.contains(char::is_whitespace) {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "The target can't contain whitespace",
+        );
+    }
+    if app_state.lock().await.find_processes(target).is_empty() {
+        return error(StatusCode::NOT_FOUND, "Couldn't find the process");
+    }
+    outcome(socket::answer(app_state, peer, &format!("{command} {target}")).await)
+}
+
+#[derive(Deserialize)]
+struct StartRequest {
+    path: String,
+}
+
+/// Starts the processes of an ecosystem file, like `fpm start` in its directory.
+async fn start_path(
+    app_state: Arc<Mutex<AppState>>,
+    peer: &Peer,
+    request: Request<Incoming>,
+) -> Response<Body> {
+    if let Some(denied) = authorize(peer, "start") {
+        return denied;
+    }
+    let body = match Limited::new(request.into_body(), MAX_BODY).collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(err) => return error(StatusCode::BAD_REQUEST, err.to_string()),
+    };
+    let start: StartRequest = match serde_json::from_slice(&body) {
+        Ok(start) => start,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err.to_string()),
+    };
+    outcome(socket::answer(app_state, peer, &format!("start {}", start.path)).await)
+}
+
+/// The answer of a socket command as JSON.
+fn outcome(answer: String) -> Response<Body> {
+    let (kind, message) = answer.trim().split_once(' ').unwrap_or((answer.trim(), ""));
+    match kind {
+        "error" => error(StatusCode::BAD_REQUEST, message),
+        _ => reply(StatusCode::OK, json!({ "ok": true, "message": message })),
+    }
+}
+
+/// The last lines of the log of one process, then whatever it writes, as server-sent events.
+async fn logs(
+    app_state: Arc<Mutex<AppState>>,
+    target: &str,
+    query: Option<&str>,
+) -> Response<Body> {
+    let processes = app_state.lock().await.find_processes(target);
+    let process = match processes.as_slice() {
+        [] => return error(StatusCode::NOT_FOUND, "Couldn't find the process"),
+        [process] => process.clone(),
+        [first, ..] => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "{} has {} instances, pick one like {}:0",
+                    first.ecosystem.title,
+                    processes.len(),
+                    first.ecosystem.title
+                ),
+            );
+        }
+    };
+    let Some(path) = process.log_path() else {
+        return error(StatusCode::NOT_FOUND, "The process doesn't keep logs");
+    };
+    let lines = query
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|pair| pair.strip_prefix("lines="))
+        .and_then(|lines| lines.parse().ok())
+        .unwrap_or(DEFAULT_TAIL);
+
+    let (sender, body) = Channel::<Bytes, Infallible>::new(16);
+    tokio::spawn(follow(path, lines, sender));
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(body.boxed())
+        .unwrap_or_default()
+}
+
+fn event(line: &str) -> Bytes {
+    Bytes::from(format!("data: {}\n\n", line.trim_end_matches('\r')))
+}
+
+/// Sends the last `lines` lines of the log, then each line appended to it until the client
+/// goes away. A log truncated by a restart is followed from its new start.
+async fn follow(path: PathBuf, lines: usize, mut sender: http_body_util::channel::Sender<Bytes>) {
+    let mut position = match tokio::fs::metadata(&path).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+    if let Ok(mut file) = tokio::fs::File::open(&path).await {
+        let start = position.saturating_sub(TAIL_WINDOW);
+        let mut tail = vec![];
+        if file.seek(SeekFrom::Start(start)).await.is_ok()
+            && file.read_to_end(&mut tail).await.is_ok()
+        {
+            position = start + tail.len() as u64;
+            let tail = String::from_utf8_lossy(&tail);
+            let mut recent: Vec<&str> = tail.lines().collect();
+            // A window that doesn't start the log may start mid-line
+            if start > 0 && !recent.is_empty() {
+                recent.remove(0);
+            }
+            for line in &recent[recent.len().saturating_sub(lines)..] {
+                if sender.send_data(event(line)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    let mut pending = String::new();
+    let mut quiet = Duration::ZERO;
+    loop {
+        tokio::time::sleep(FOLLOW_INTERVAL).await;
+        let length = tokio::fs::metadata(&path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        if length < position {
+            position = 0;
+            pending.clear();
+        }
+        let mut appended = vec![];
+        if length > position
+            && let Ok(mut file) = tokio::fs::File::open(&path).await
+            && file.seek(SeekFrom::Start(position)).await.is_ok()
+        {
+            let _ = (&mut file)
+                .take(length - position)
+                .read_to_end(&mut appended)
+                .await;
+        }
+        position += appended.len() as u64;
+        if appended.is_empty() {
+            quiet += FOLLOW_INTERVAL;
+            if quiet >= KEEPALIVE_INTERVAL {
+                quiet = Duration::ZERO;
+                if sender
+                    .send_data(Bytes::from(": keepalive\n\n"))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            continue;
+        }
+        quiet = Duration::ZERO;
+        pending += &String::from_utf8_lossy(&appended);
+        while let Some(end) = pending.find('\n') {
+            let line: String = pending.drain(..=end).collect();
+            if sender.send_data(event(&line[..end])).await.is_err() {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{
+    access::{self, Peer},
+    project_dir,
+};
+
+pub const AUDIT_NAME: &str = "audit.log";
+
+/// One line of the audit log.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Entry {
+    /// RFC 3339
+    pub time: String,
+    /// Who sent a command over the socket
+    pub uid: Option<u32>,
+    pub pid: Option<i32>,
+    /// The token and address of a remote client
+    pub remote: Option<String>,
+    pub command: String,
+    /// The process or file the command was about
+    pub target: Option<String>,
+    pub args: String,
+    pub ok: bool,
+    /// What the daemon answered
+    pub outcome: String,
+}
+
+pub fn audit_path() -> Option<PathBuf> {
+    project_dir().map(|project_dir| project_dir.data_dir().join(AUDIT_NAME))
+}
+
+/// What a command acted on: the name of a process started from JSON, otherwise the first
+/// argument.
+fn target(command: &str, args: &str) -> Option<String> {
+    if matches!(command, "backup" | "upgrade") {
+        return None;
+    }
+    if args.starts_with('{') {
+        let request: serde_json::Value = serde_json::from_str(args).ok()?;
+        return request
+            .get("name")
+            .or_else(|| request.get("ecosystem")?.get("title"))
+            .and_then(|name| name.as_str())
+            .map(str::to_string);
+    }
+    args.split_whitespace().next().map(str::to_string)
+}
+
+/// Appends the command to the audit log if it changes anything, refused ones included.
+pub async fn record(peer: &Peer, message: &str, answer: &str) {
+    let (command, args) = match message.trim().split_once(char::is_whitespace) {
+        Some((command, args)) => (command, args.trim()),
+        None => (message.trim(), ""),
+    };
+    if access::is_read_only(command) {
+        return;
+    }
+    let (ok, outcome) = match answer.trim().split_once(' ') {
+        Some((kind, outcome)) => (kind != "error", outcome.trim().to_string()),
+        None => (answer.trim() != "error", String::new()),
+    };
+    let entry = Entry {
+        time: Utc::now().to_rfc3339(),
+        uid: match peer {
+            Peer::Local(cred) => Some(cred.uid()),
+            Peer::Remote { .. } => None,
+        },
+        pid: match peer {
+            Peer::Local(cred) => cred.pid(),
+            Peer::Remote { .. } => None,
+        },
+        remote: match peer {
+            Peer::Local(_) => None,
+            Peer::Remote { .. } => Some(peer.to_string()),
+        },
+        command: command.to_string(),
+        target: target(command, args),
+        args: args.to_string(),
+        ok,
+        outcome,
+    };
+    if let Err(err) = append(&entry).await {
+        eprintln!("Can't write the audit log: {err}");
+    }
+}
+
+async fn append(entry: &Entry) -> Result<(), Box<dyn std::error::Error>> {
+    let path = audit_path().ok_or("can't get ProjectDir")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    // Only the daemon's user may read who did what
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+/// Every entry from `since` on, oldest first. Lines that can't be parsed are skipped.
+pub async fn read(since: Option<DateTime<Utc>>) -> Result<Vec<Entry>, Box<dyn std::error::Error>> {
+    let path = audit_path().ok_or("can't get ProjectDir")?;
+    let content = match fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(Box::new(err)),
+    };
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Entry>(line).ok())
+        .filter(|entry| {
+            since.is_none_or(|since| {
+                DateTime::parse_from_rfc3339(&entry.time).is_ok_and(|time| time >= since)
+            })
+        })
+        .collect())
+}
+
+/// `--since` as a time ago like `30m`, `12h` or `7d`, a local date with an optional time, or
+/// RFC 3339.
+pub fn parse_since(text: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let text = text.trim();
+    let invalid = || format!("invalid time \"{text}\", use e.g. 30m, 12h, 7d or 2025-01-31 03:00");
+    if let Some((index, unit)) = text.char_indices().last()
+        && let Ok(count) = text[..index].parse::<i64>()
+    {
+        let ago = match unit {
+            's' => Duration::try_seconds(count),
+            'm' => Duration::try_minutes(count),
+            'h' => Duration::try_hours(count),
+            'd' => Duration::try_days(count),
+            _ => None,
+        };
+        return ago
+            .and_then(|ago| now.checked_sub_signed(ago))
+            .ok_or_else(invalid);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let naive = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .ok()
+            .map(|date| date.and_time(NaiveTime::MIN))
+    })
+    .ok_or_else(invalid)?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_since() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 31, 12, 0, 0).unwrap();
+        assert_eq!(
+            parse_since("30m", now),
+            Ok(Utc.with_ymd_and_hms(2025, 1, 31, 11, 30, 0).unwrap())
+        );
+        assert_eq!(
+            parse_since("7d", now),
+            Ok(Utc.with_ymd_and_hms(2025, 1, 24, 12, 0, 0).unwrap())
+        );
+        assert_eq!(
+            parse_since("2025-01-31T03:00:00+00:00", now),
+            Ok(Utc.with_ymd_and_hms(2025, 1, 31, 3, 0, 0).unwrap())
+        );
+        assert!(parse_since("2025-01-31 03:00", now).is_ok());
+        assert!(parse_since("2025-01-31", now).is_ok());
+        assert!(parse_since("3x", now).is_err());
+        assert!(parse_since("yesterday", now).is_err());
+    }
+}
@@ -1,32 +1,59 @@
-use std::{error::Error, fmt::Display, process, sync::Arc};
+use std::{error::Error, process, sync::Arc};
 
-use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 
 use crate::{
-    processes::{Ecosystem, Process, ProcessStatus},
+    processes::{Ecosystem, Process},
     project_dir,
 };
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct Backup {
-    max_id: u32,
-    process: Vec<BackupProcess>,
+    pub max_id: u32,
+    #[serde(default)]
+    pub process: Vec<BackupProcess>,
 }
 
-#[derive(Deserialize, Debug)]
+impl Backup {
+    pub async fn from_processes(max_id: u32, processes: Vec<Arc<Process>>) -> Self {
+        let mut backup_processes = Vec::with_capacity(processes.len());
+        for process in processes {
+            backup_processes.push(BackupProcess::from_process(process).await);
+        }
+        backup_processes.sort_by_key(|process| process.id);
+        Self {
+            max_id,
+            process: backup_processes,
+        }
+    }
+
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
 pub struct BackupProcess {
     pub id: u32,
-    pub ecosystem: Ecosystem,
+    #[serde(default)]
+    pub instance: u32,
     pub path: String,
     pub should_stop: bool,
+    pub ecosystem: Ecosystem,
 }
 
 impl BackupProcess {
-    pub fn new(id: u32, ecosystem: Ecosystem, path: String, should_stop: bool) -> Self {
+    pub fn new(
+        id: u32,
+        instance: u32,
+        ecosystem: Ecosystem,
+        path: String,
+        should_stop: bool,
+    ) -> Self {
         Self {
             id,
+            instance,
             ecosystem,
             path,
             should_stop,
@@ -40,6 +67,7 @@ impl BackupProcess {
         };
         Self::new(
             process.id,
+            process.instance,
             process.ecosystem.clone(),
             process.path.clone(),
             should_stop,
@@ -47,16 +75,6 @@ impl BackupProcess {
     }
 }
 
-impl Display for BackupProcess {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "id={}\npath=\"{}\"\nshould_stop={}\n{}",
-            self.id, self.path, self.should_stop, self.ecosystem
-        )
-    }
-}
-
 pub async fn load_backup() -> Vec<BackupProcess> {
     let project_dir = match project_dir() {
         Some(project_dir) => project_dir,
@@ -93,11 +111,7 @@ pub async fn load_backup() -> Vec<BackupProcess> {
     backup.process
 }
 pub async fn write_backup(max_id: u32, processes: Vec<Arc<Process>>) -> Result<(), Box<dyn Error>> {
-    let mut backup_string = format!("max_id = {}", max_id);
-    for process in processes {
-        let process = BackupProcess::from_process(process).await;
-        backup_string = format!("{}\n[[process]]\n{}", backup_string, process);
-    }
+    let backup_string = Backup::from_processes(max_id, processes).await.to_toml()?;
 
     let project_dir = match project_dir() {
         Some(project_dir) => project_dir,
@@ -108,6 +122,6 @@ pub async fn write_backup(max_id: u32, processes: Vec<Arc<Process>>) -> Result<(
     };
     let data_dir = project_dir.data_dir();
 
-    fs::write(data_dir.join("backup.toml"), backup_string).await;
+    fs::write(data_dir.join("backup.toml"), backup_string).await?;
     Ok(())
 }
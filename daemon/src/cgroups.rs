@@ -0,0 +1,250 @@
+use std::{
+    ffi::CString,
+    fs::{self, OpenOptions},
+    io,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use nix::unistd::geteuid;
+use tokio::process::Command;
+
+use crate::processes::{CgroupLimits, Limit};
+
+/// The leaf the daemon moves itself into. A cgroup with processes in it can't hand
+/// controllers down to its children, so the daemon can't stay where it was started.
+const DAEMON_LEAF: &str = "fpmd";
+const CONTROLLERS: [&str; 3] = ["cpu", "memory", "pids"];
+/// Set to `1` by systemd on the cgroup of a unit with `Delegate=yes`, `user.` by a user manager
+const DELEGATE_XATTRS: [&str; 2] = ["trusted.delegate", "user.delegate"];
+/// Files systemd hands to the user of a delegated unit along with the directory
+const DELEGATED_FILES: [&str; 3] = [".", "cgroup.procs", "cgroup.subtree_control"];
+
+/// The cgroup v2 subtree delegated to the daemon, `None` when there is none to use.
+static ROOT: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Sets up the delegated subtree, if the daemon was given one. Without it every process
+/// runs in the daemon's cgroup, and cgroup limits are refused.
+pub fn init() {
+    let root = match find_root() {
+        Ok(root) => {
+            println!("Using cgroup subtree {}", root.display());
+            Some(root)
+        }
+        Err(err) => {
+            eprintln!("Cgroup limits are disabled, {err}");
+            None
+        }
+    };
+    let _ = ROOT.set(root);
+}
+
+fn find_root() -> Result<PathBuf, String> {
+    let myself = procfs::process::Process::myself().map_err(|err| err.to_string())?;
+    let mount = myself
+        .mountinfo()
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .find(|mount| mount.fs_type == "cgroup2")
+        .map(|mount| mount.mount_point)
+        .ok_or("no cgroup v2 hierarchy is mounted")?;
+    let own = myself
+        .cgroups()
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .find(|cgroup| cgroup.hierarchy == 0)
+        .map(|cgroup| mount.join(cgroup.pathname.trim_start_matches('/')))
+        .ok_or("the daemon isn't in a cgroup v2 hierarchy")?;
+    // After an upgrade the daemon is already in its leaf
+    let root = match own.parent() {
+        Some(parent) if own.ends_with(DAEMON_LEAF) => parent.to_path_buf(),
+        _ => own,
+    };
+    if !is_delegated(&root, &mount) {
+        return Err(format!(
+            "{} isn't delegated to fpmd, run it in a unit with Delegate=yes",
+            root.display()
+        ));
+    }
+    let available = fs::read_to_string(root.join("cgroup.controllers")).unwrap_or_default();
+    if !CONTROLLERS
+        .iter()
+        .any(|controller| available.split_whitespace().any(|name| name == *controller))
+    {
+        return Err(format!(
+            "none of {} is available in {}",
+            CONTROLLERS.join(", "),
+            root.display()
+        ));
+    }
+
+    let leaf = root.join(DAEMON_LEAF);
+    fs::create_dir_all(&leaf)
+        .and_then(|_| fs::write(leaf.join("cgroup.procs"), std::process::id().to_string()))
+        .map_err(|err| format!("can't move into {}: {err}", leaf.display()))?;
+    for controller in CONTROLLERS {
+        if !available.split_whitespace().any(|name| name == controller) {
+            continue;
+        }
+        if let Err(err) = fs::write(
+            root.join("cgroup.subtree_control"),
+            format!("+{controller}"),
+        ) {
+            eprintln!("Can't enable the {controller} controller: {err}");
+        }
+    }
+    Ok(root)
+}
+
+/// Whether `root` was handed to the daemon. Being able to write to it proves nothing when
+/// running as root, so it has to be the top of the hierarchy the daemon sees, like in a
+/// container, or marked by systemd: delegated cgroups get a `delegate` xattr, and belong to
+/// the unit's user when it isn't root.
+fn is_delegated(root: &Path, mount: &Path) -> bool {
+    if root == mount {
+        return true;
+    }
+    if DELEGATE_XATTRS.iter().any(|name| has_flag(root, name)) {
+        return true;
+    }
+    let euid = geteuid();
+    !euid.is_root()
+        && DELEGATED_FILES.iter().all(|file| {
+            fs::metadata(root.join(file)).is_ok_and(|metadata| metadata.uid() == euid.as_raw())
+        })
+}
+
+/// Whether the extended attribute `name` of `path` is `1`.
+fn has_flag(path: &Path, name: &str) -> bool {
+    let (Ok(path), Ok(name)) = (
+        CString::new(path.as_os_str().as_bytes()),
+        CString::new(name),
+    ) else {
+        return false;
+    };
+    let mut value = [0u8; 8];
+    // SAFETY: both names are NUL terminated and the buffer length is passed along
+    let len = unsafe {
+        libc::getxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_mut_ptr().cast(),
+            value.len(),
+        )
+    };
+    len > 0 && value[..len as usize] == *b"1"
+}
+
+pub fn available() -> bool {
+    ROOT.get().is_some_and(|root| root.is_some())
+}
+
+/// What the kernel counted for everything in a cgroup.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub memory: Option<u64>,
+    pub cpu_usec: Option<u64>,
+    pub pids: Option<u64>,
+}
+
+/// The cgroup of one process, holding its child and everything the child starts.
+#[derive(Debug, Clone)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    pub fn of(id: u32) -> Option<Self> {
+        let root = ROOT.get()?.as_ref()?;
+        Some(Self {
+            path: root.join(format!("process-{id}")),
+        })
+    }
+
+    /// Creates the cgroup if needed and writes the limits, resetting the ones not set.
+    pub fn create(&self, limits: &CgroupLimits) -> io::Result<()> {
+        fs::create_dir_all(&self.path)?;
+        let memory_max = limits.memory_max.as_ref().map(max).transpose()?;
+        let pids_max = limits.pids_max.as_ref().map(max).transpose()?;
+        self.set("memory.max", memory_max)?;
+        self.set("cpu.max", limits.cpu_max.clone())?;
+        self.set("pids.max", pids_max)?;
+        Ok(())
+    }
+
+    fn set(&self, file: &str, value: Option<String>) -> io::Result<()> {
+        let path = self.path.join(file);
+        match value {
+            Some(value) if !path.exists() => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("can't set {file} to {value}, the controller isn't delegated to fpmd"),
+            )),
+            Some(value) => fs::write(path, value),
+            None if path.exists() => fs::write(path, "max"),
+            None => Ok(()),
+        }
+    }
+
+    /// Moves the child into the cgroup between fork and exec, before it can start anything.
+    pub fn apply(&self, command: &mut Command) -> io::Result<()> {
+        let procs = OpenOptions::new()
+            .write(true)
+            .open(self.path.join("cgroup.procs"))?;
+        unsafe {
+            command.pre_exec(move || {
+                // "0" is whoever writes it
+                nix::unistd::write(&procs, b"0")?;
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// SIGKILLs everything in the cgroup at once, escaped descendants included.
+    pub fn kill(&self) {
+        if let Err(err) = fs::write(self.path.join("cgroup.kill"), "1") {
+            eprintln!("Can't kill cgroup {}: {err}", self.path.display());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        fs::read_to_string(self.path.join("cgroup.procs"))
+            .map(|procs| procs.trim().is_empty())
+            .unwrap_or(true)
+    }
+
+    pub fn usage(&self) -> Usage {
+        let read = |file: &str| fs::read_to_string(self.path.join(file)).ok();
+        Usage {
+            memory: read("memory.current").and_then(|value| value.trim().parse().ok()),
+            cpu_usec: read("cpu.stat").and_then(|stat| {
+                stat.lines()
+                    .find_map(|line| line.strip_prefix("usage_usec "))
+                    .and_then(|value| value.trim().parse().ok())
+            }),
+            pids: read("pids.current").and_then(|value| value.trim().parse().ok()),
+        }
+    }
+
+    /// Removes the cgroup once the process is deleted. Fails while anything still runs in it.
+    pub fn remove(&self) {
+        if self.path.exists()
+            && let Err(err) = fs::remove_dir(&self.path)
+        {
+            eprintln!("Can't remove cgroup {}: {err}", self.path.display());
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn max(limit: &Limit) -> io::Result<String> {
+    match limit.value() {
+        Ok(Some(value)) => Ok(value.to_string()),
+        Ok(None) => Ok("max".to_string()),
+        Err(err) => Err(io::Error::new(io::ErrorKind::InvalidInput, err)),
+    }
+}
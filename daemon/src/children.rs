@@ -0,0 +1,180 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use nix::{
+    errno::Errno,
+    sys::wait::{WaitPidFlag, WaitStatus, waitpid},
+    unistd::Pid,
+};
+use procfs::WithCurrentSystemInfo;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{processes::ID_ENV, project_dir};
+
+/// A child we spawned, written to disk so the next daemon can pick it up instead of
+/// starting a duplicate. `start_time` comes from `/proc/<pid>/stat` and guards against
+/// the pid being reused by an unrelated process.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct ChildRecord {
+    pub id: u32,
+    pub pid: u32,
+    pub start_time: u64,
+}
+
+/// Pids of the children some task is already waiting on, with the id of their process. The
+/// reaper has to leave them alone, reaping one would steal its exit status.
+static WAITED: LazyLock<Mutex<HashMap<u32, u32>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub fn track(id: u32, pid: u32) {
+    WAITED.lock().unwrap().insert(pid, id);
+}
+
+pub fn untrack(pid: u32) {
+    WAITED.lock().unwrap().remove(&pid);
+}
+
+pub fn is_waited(pid: u32) -> bool {
+    WAITED.lock().unwrap().contains_key(&pid)
+}
+
+fn children_dir() -> Option<PathBuf> {
+    project_dir().map(|project_dir| project_dir.data_dir().join("children"))
+}
+
+fn record_path(id: u32) -> Option<PathBuf> {
+    children_dir().map(|dir| dir.join(format!("{id}.toml")))
+}
+
+pub fn start_time(pid: u32) -> Option<u64> {
+    let process = procfs::process::Process::new(pid as i32).ok()?;
+    Some(process.stat().ok()?.starttime)
+}
+
+pub fn started_at(pid: u32) -> Option<DateTime<Utc>> {
+    let process = procfs::process::Process::new(pid as i32).ok()?;
+    let started_at = process.stat().ok()?.starttime().get().ok()?;
+    Some(started_at.with_timezone(&Utc))
+}
+
+fn has_marker(pid: u32, id: u32) -> bool {
+    let environ = match procfs::process::Process::new(pid as i32).and_then(|p| p.environ()) {
+        Ok(environ) => environ,
+        Err(_) => return false,
+    };
+    environ.get(&OsString::from(ID_ENV)) == Some(&OsString::from(id.to_string()))
+}
+
+impl ChildRecord {
+    /// Whether the recorded pid still belongs to the child we spawned.
+    pub fn is_alive(&self) -> bool {
+        start_time(self.pid) == Some(self.start_time)
+    }
+}
+
+pub async fn remember(id: u32, pid: u32) {
+    track(id, pid);
+    let (Some(dir), Some(path)) = (children_dir(), record_path(id)) else {
+        return;
+    };
+    let Some(start_time) = start_time(pid) else {
+        return;
+    };
+    let record = ChildRecord {
+        id,
+        pid,
+        start_time,
+    };
+    let _ = fs::create_dir_all(&dir).await;
+    match toml::to_string(&record) {
+        Ok(content) => {
+            if let Err(err) = fs::write(path, content).await {
+                eprintln!("Can't save pid of process {id}: {err}");
+            }
+        }
+        Err(err) => eprintln!("Can't save pid of process {id}: {err}"),
+    }
+}
+
+async fn remove_record(id: u32) {
+    if let Some(path) = record_path(id) {
+        let _ = fs::remove_file(path).await;
+    }
+}
+
+/// The recorded child of process `id` is gone, other children of it may still be waited on.
+pub async fn release(id: u32, pid: u32) {
+    untrack(pid);
+    remove_record(id).await;
+}
+
+pub async fn forget(id: u32) {
+    WAITED.lock().unwrap().retain(|_, waited| *waited != id);
+    remove_record(id).await;
+}
+
+/// Returns the child left behind by a previous daemon for process `id`, if it is still
+/// running and still carries our marker.
+pub async fn recall(id: u32) -> Option<ChildRecord> {
+    let content = fs::read_to_string(record_path(id)?).await.ok()?;
+    let record: ChildRecord = toml::from_str(&content).ok()?;
+    if record.id == id && record.is_alive() && has_marker(record.pid, id) {
+        Some(record)
+    } else {
+        forget(id).await;
+        None
+    }
+}
+
+/// Drops records of processes the daemon no longer knows about. Their children are left
+/// running untouched, there is nothing left to supervise them with.
+pub async fn forget_unknown(known: &HashSet<u32>) {
+    let Some(dir) = children_dir() else {
+        return;
+    };
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let id = entry
+            .path()
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u32>().ok());
+        if let Some(id) = id.filter(|id| !known.contains(id)) {
+            if let Some(record) = recall(id).await {
+                println!(
+                    "Child {} of unknown process {id} is left running",
+                    record.pid
+                );
+            }
+            forget(id).await;
+        }
+    }
+}
+
+/// Waits for an adopted child to exit. If it is still our child (the daemon was re-executed
+/// in place) the exit status is reaped, otherwise the pid is polled until it goes away and
+/// the status stays unknown.
+pub async fn wait_adopted(record: ChildRecord) -> Option<WaitStatus> {
+    let pid = Pid::from_raw(record.pid as i32);
+    loop {
+        match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) => {}
+            Ok(status) => return Some(status),
+            Err(Errno::ECHILD) => {
+                if !record.is_alive() {
+                    return None;
+                }
+            }
+            Err(_) => return None,
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
@@ -10,8 +10,32 @@ pub use backup::backup;
 mod restart;
 pub use restart::restart;
 
-mod status;
+pub mod status;
 pub use status::status;
 
-mod delete;
+pub mod delete;
 pub use delete::delete;
+
+mod export;
+pub use export::export;
+
+mod import;
+pub use import::import;
+
+mod upgrade;
+pub use upgrade::upgrade;
+
+mod describe;
+pub use describe::describe;
+
+mod scale;
+pub use scale::scale;
+
+mod reload;
+pub use reload::reload;
+
+mod run;
+pub use run::run;
+
+mod audit;
+pub use audit::audit;
@@ -0,0 +1,53 @@
+use std::{error::Error, sync::Arc};
+
+use chrono::{DateTime, Local, Utc};
+use tokio::sync::Mutex;
+
+use crate::{AppState, audit, socket::Response};
+
+/// Mutating commands from the audit log, all of them or the ones since the time in `arg`.
+pub async fn audit(
+    _app_state: Arc<Mutex<AppState>>,
+    arg: String,
+) -> Result<Response, Box<dyn Error>> {
+    let since = match arg.trim() {
+        "" => None,
+        since => match audit::parse_since(since, Utc::now()) {
+            Ok(since) => Some(since),
+            Err(err) => return Ok(Response::ErrorData(err)),
+        },
+    };
+    let entries = audit::read(since).await?;
+    if entries.is_empty() {
+        return Ok(Response::Successfully(Some("Nothing in the audit log")));
+    }
+    let mut output = String::new();
+    for entry in entries {
+        let time = DateTime::parse_from_rfc3339(&entry.time)
+            .map(|time| {
+                time.with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or(entry.time);
+        let who = match (&entry.remote, entry.uid) {
+            (Some(remote), _) => remote.clone(),
+            (None, Some(uid)) => match entry.pid {
+                Some(pid) => format!("uid {uid} (pid {pid})"),
+                None => format!("uid {uid}"),
+            },
+            (None, None) => "unknown".to_string(),
+        };
+        let outcome = match (entry.ok, entry.outcome.is_empty()) {
+            (true, true) => "ok".to_string(),
+            (true, false) => format!("ok, {}", entry.outcome),
+            (false, _) => format!("failed, {}", entry.outcome),
+        };
+        output += format!(
+            "{} {}: {} {} -> {}\n",
+            time, who, entry.command, entry.args, outcome
+        )
+        .as_str();
+    }
+    Ok(Response::Data(output.trim_end().to_string()))
+}
@@ -0,0 +1,192 @@
+use std::{error::Error, sync::Arc};
+
+use chrono::Local;
+use tokio::sync::Mutex;
+
+use crate::{
+    AppState,
+    cgroups::Cgroup,
+    commands::status::format_duration,
+    processes::{Process, REDACTED},
+    socket::Response,
+};
+
+/// Everything known about a process, or about each instance of it.
+pub async fn describe(
+    app_state: Arc<Mutex<AppState>>,
+    arg: String,
+) -> Result<Response, Box<dyn Error>> {
+    let processes = app_state.lock().await.find_processes(arg.trim());
+    if processes.is_empty() {
+        return Ok(Response::Error("Process not found"));
+    }
+    let mut descriptions = Vec::with_capacity(processes.len());
+    for process in processes {
+        descriptions.push(describe_process(&process).await);
+    }
+    Ok(Response::Data(descriptions.join("\n")))
+}
+
+async fn describe_process(process: &Process) -> String {
+    let process_status = process.status();
+    let state = process.state.lock().await;
+    let mut output = format!("{}. {}\n", process.id, process.name());
+    if let Some(description) = &process.ecosystem.description {
+        output += format!("description: {}\n", description).as_str();
+    }
+    output += format!("path: {}\n", process.path).as_str();
+    output += format!("start: {}\n", process.ecosystem.start).as_str();
+    if let Some(user) = &process.ecosystem.user {
+        let group = match &process.ecosystem.group {
+            Some(group) => format!(", group {group}"),
+            None => String::new(),
+        };
+        output += format!("user: {}{}\n", user, group).as_str();
+    }
+    for (name, value) in process.ecosystem.env.iter().flatten() {
+        output += format!("env: {}={}\n", name, value).as_str();
+    }
+    for (name, path) in process.ecosystem.env_secrets.iter().flatten() {
+        output += format!("env: {}={} (secret from {})\n", name, REDACTED, path).as_str();
+    }
+    if let Some(logs) = &process.ecosystem.logs {
+        output += format!("logs: {}\n", logs).as_str();
+    }
+    if let Some(depends_on) = &process.ecosystem.depends_on {
+        output += format!("depends on: {}\n", depends_on.join(", ")).as_str();
+    }
+    let (cron, label, next) = if process.ecosystem.is_job() {
+        (&process.ecosystem.schedule, "schedule", "next run")
+    } else {
+        (
+            &process.ecosystem.cron_restart,
+            "cron restart",
+            "next restart",
+        )
+    };
+    if let Some(cron) = cron {
+        let timezone = process
+            .ecosystem
+            .timezone
+            .as_deref()
+            .unwrap_or("local time");
+        output += format!("{}: {} ({})\n", label, cron, timezone).as_str();
+        if let Some(next_run) = process.ecosystem.next_run().filter(|_| !state.should_stop) {
+            output += format!(
+                "{}: {}\n",
+                next,
+                next_run.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+            )
+            .as_str();
+        }
+    }
+    output += format!("status: {}\n", process_status).as_str();
+    if let Some(pid) = state.uid.filter(|_| process_status.is_active()) {
+        output += format!("pid: {}\n", pid).as_str();
+    }
+    if let Some(started_at) = state.started_at {
+        output += format!(
+            "started at: {}\n",
+            started_at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+        )
+        .as_str();
+    }
+    if let Some(uptime) = state.uptime(process_status) {
+        output += format!("uptime: {}\n", format_duration(uptime)).as_str();
+    }
+    if process.ecosystem.runs_to_completion() {
+        if let Some(timeout) = process.ecosystem.timeout {
+            output += format!("timeout: {}s\n", timeout).as_str();
+        }
+        output += format!(
+            "attempts: {} of {}\n",
+            state.attempts,
+            process.ecosystem.retries.unwrap_or(0) + 1
+        )
+        .as_str();
+    } else {
+        output += format!("restarts: {}\n", state.restarts).as_str();
+    }
+    if let Some(last_exit) = state.last_exit {
+        output += format!("last exit: {}\n", last_exit).as_str();
+    }
+    if let Some(last_error) = &state.last_error {
+        output += format!("last error: {}\n", last_error).as_str();
+    }
+    if let Some(rlimit) = &process.ecosystem.rlimit {
+        let limits: Vec<String> = [
+            ("nofile", &rlimit.nofile),
+            ("nproc", &rlimit.nproc),
+            ("core", &rlimit.core),
+            ("as", &rlimit.address_space),
+        ]
+        .into_iter()
+        .filter_map(|(name, limit)| Some(format!("{name}={}", limit.as_ref()?)))
+        .collect();
+        output += format!("rlimit: {}\n", limits.join(", ")).as_str();
+    }
+    if let Some(nice) = process.ecosystem.nice {
+        output += format!("nice: {}\n", nice).as_str();
+    }
+    if let Some(ionice) = process.ecosystem.ionice {
+        output += format!("ionice: {}\n", ionice).as_str();
+    }
+    if let Some(cpus) = &process.ecosystem.cpu_affinity {
+        let cpus: Vec<String> = cpus.iter().map(|cpus| cpus.to_string()).collect();
+        output += format!("cpu affinity: {}\n", cpus.join(", ")).as_str();
+    }
+    if let Some(oom_score_adj) = process.ecosystem.oom_score_adj {
+        output += format!("oom score adj: {}\n", oom_score_adj).as_str();
+    }
+    if let Some(sandbox) = &process.ecosystem.sandbox {
+        output += format!("sandbox: {}\n", sandbox).as_str();
+    }
+    for warning in &state.warnings {
+        output += format!("sandbox warning: {}\n", warning).as_str();
+    }
+    if let Some(cgroup) = Cgroup::of(process.id) {
+        output += format!("cgroup: {}\n", cgroup.path().display()).as_str();
+        let usage = cgroup.usage();
+        if let Some(memory) = usage.memory {
+            output += format!("memory: {:.1} MiB\n", memory as f64 / 1048576.0).as_str();
+        }
+        if let Some(cpu_usec) = usage.cpu_usec {
+            output += format!("cpu time: {:.2}s\n", cpu_usec as f64 / 1_000_000.0).as_str();
+        }
+        if let Some(pids) = usage.pids {
+            output += format!("pids: {}\n", pids).as_str();
+        }
+    }
+    if !state.descendants.is_empty() {
+        let mut descendants: Vec<&u32> = state.descendants.keys().collect();
+        descendants.sort();
+        let descendants: Vec<String> = descendants.iter().map(|pid| pid.to_string()).collect();
+        output += format!("descendants: {}\n", descendants.join(", ")).as_str();
+    }
+    if !state.runs.is_empty() {
+        output += "runs:\n";
+        for run in &state.runs {
+            output += format!(
+                "\t{} took {}, {}\n",
+                run.started_at
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S"),
+                format_duration(run.duration),
+                run.exit
+                    .map(|exit| exit.to_string())
+                    .unwrap_or_else(|| "still running".to_string())
+            )
+            .as_str();
+        }
+    }
+    output += "history:\n";
+    for (at, status) in &state.history {
+        output += format!(
+            "\t{} {}\n",
+            at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+            status
+        )
+        .as_str();
+    }
+    output
+}
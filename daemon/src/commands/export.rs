@@ -0,0 +1,24 @@
+use std::{error::Error, sync::Arc};
+
+use tokio::sync::Mutex;
+
+use crate::{AppState, backup::Backup, processes::Process, socket::Response};
+
+pub async fn export(
+    app_state: Arc<Mutex<AppState>>,
+    _arg: String,
+) -> Result<Response, Box<dyn Error>> {
+    let (processes, max_id) = {
+        let app_state_guard = app_state.lock().await;
+        (app_state_guard.processes.clone(), app_state_guard.id)
+    };
+    let processes_vec: Vec<Arc<Process>> = processes.values().cloned().collect();
+
+    match Backup::from_processes(max_id, processes_vec)
+        .await
+        .to_toml()
+    {
+        Ok(dump) => Ok(Response::Data(dump)),
+        Err(_) => Ok(Response::Error("Couldn't serialize the processes")),
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use tokio::{fs, sync::Mutex};
+
+use crate::{
+    AppState,
+    backup::{Backup, BackupProcess},
+    dependencies,
+    processes::find_executable,
+    socket::Response,
+};
+
+/// What `fpm import` sends, as JSON so paths with spaces stay whole.
+#[derive(Deserialize)]
+struct ImportRequest {
+    /// The dump file
+    path: String,
+    /// `old=new` path prefixes
+    #[serde(default)]
+    path_prefix_map: Vec<String>,
+}
+
+pub async fn import(
+    app_state: Arc<Mutex<AppState>>,
+    arg: String,
+) -> Result<Response, Box<dyn Error>> {
+    let request: ImportRequest = match serde_json::from_str(&arg) {
+        Ok(request) => request,
+        Err(_) => return Ok(Response::Error("Bad usage: import <file> [old=new...]")),
+    };
+    let mut prefix_map: Vec<(PathBuf, PathBuf)> = vec![];
+    for mapping in &request.path_prefix_map {
+        match mapping.split_once('=') {
+            Some((old, new)) if !old.is_empty() => {
+                prefix_map.push((PathBuf::from(old), PathBuf::from(new)))
+            }
+            _ => {
+                return Ok(Response::Error(
+                    "Invalid path prefix mapping, expected old=new",
+                ));
+            }
+        }
+    }
+
+    let dump = match fs::read_to_string(&request.path).await {
+        Ok(dump) => dump,
+        Err(_) => return Ok(Response::Error("Couldn't read the dump file")),
+    };
+    let backup: Backup = match toml::from_str(&dump) {
+        Ok(backup) => backup,
+        Err(_) => return Ok(Response::Error("The dump file is not a valid fpm export")),
+    };
+
+    // Validate everything first so a bad entry doesn't leave a half-applied import behind
+    let mut problems: Vec<String> = vec![];
+    let mut imported: Vec<BackupProcess> = vec![];
+    for entry in backup.process {
+        let title = entry.ecosystem.title.clone();
+        let path = remap_path(&entry.path, &prefix_map);
+        if !Path::new(&path).is_dir() {
+            problems.push(format!("{title}: directory {path} does not exist"));
+        }
+        match entry.ecosystem.program() {
+            // Relative to the process directory, where it is started from
+            Some(program) if program.contains('/') => {
+                if !Path::new(&path).join(program).is_file() {
+                    problems.push(format!("{title}: {program} was not found"));
+                }
+            }
+            Some(program) => {
+                if find_executable(program).is_none() {
+                    problems.push(format!("{title}: {program} was not found"));
+                }
+            }
+            None => problems.push(format!("{title}: start is an empty list")),
+        }
+        if let Err(err) = entry.ecosystem.validate(&path) {
+            problems.push(err);
+        }
+        // Instances of one entry share the title, they are told apart by their number
+        if !app_state.lock().await.find_processes(&title).is_empty() {
+            problems.push(format!("{title}: a process with this title already exists"));
+        } else if imported.iter().any(|imported| {
+            imported.ecosystem.title == title && imported.instance == entry.instance
+        }) {
+            problems.push(format!(
+                "{title}: instance {} is listed more than once",
+                entry.instance
+            ));
+        }
+        imported.push(BackupProcess { path, ..entry });
+    }
+    if !problems.is_empty() {
+        return Ok(Response::ErrorData(format!(
+            "Nothing was imported:\n\t{}",
+            problems.join("\n\t")
+        )));
+    }
+
+    let mut to_start = vec![];
+    for entry in imported {
+        let process =
+            app_state
+                .lock()
+                .await
+                .insert_process(entry.ecosystem, entry.path, entry.instance);
+        if entry.should_stop {
+            process.state.lock().await.should_stop = true;
+        } else {
+            to_start.push(process);
+        }
+    }
+    // Waiting for dependencies to be ready can take a while, the answer doesn't wait for it
+    tokio::spawn(async move {
+        if let Err(err) = dependencies::start_in_order(app_state, to_start.clone(), false).await {
+            eprintln!("Can't order imported processes, starting them all at once: {err}");
+            for process in to_start {
+                process.start().await;
+            }
+        }
+    });
+    Ok(Response::Successfully(Some(
+        "Processes imported. Don't forget to make a backup:\n\tfpm backup",
+    )))
+}
+
+/// Rewrites `path` with the longest matching `old=new` prefix, comparing whole path components.
+fn remap_path(path: &str, prefix_map: &[(PathBuf, PathBuf)]) -> String {
+    let path = Path::new(path);
+    prefix_map
+        .iter()
+        .filter_map(|(old, new)| {
+            path.strip_prefix(old).ok().map(|rest| {
+                let remapped = if rest.as_os_str().is_empty() {
+                    new.clone()
+                } else {
+                    new.join(rest)
+                };
+                (old.components().count(), remapped)
+            })
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .map_or(path.to_path_buf(), |(_, remapped)| remapped)
+        .to_string_lossy()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs, os::unix::fs::PermissionsExt};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::processes::{Ecosystem, StartCommand};
+
+    fn map(pairs: &[(&str, &str)]) -> Vec<(PathBuf, PathBuf)> {
+        pairs
+            .iter()
+            .map(|(old, new)| (PathBuf::from(old), PathBuf::from(new)))
+            .collect()
+    }
+
+    #[test]
+    fn remaps_whole_components_only() {
+        let prefix_map = map(&[("/srv/app", "/opt/app")]);
+        assert_eq!(remap_path("/srv/app", &prefix_map), "/opt/app");
+        assert_eq!(remap_path("/srv/app/web", &prefix_map), "/opt/app/web");
+        assert_eq!(
+            remap_path("/srv/application", &prefix_map),
+            "/srv/application"
+        );
+        assert_eq!(remap_path("/srv", &prefix_map), "/srv");
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let prefix_map = map(&[("/srv", "/data"), ("/srv/app", "/opt/app")]);
+        assert_eq!(remap_path("/srv/app/web", &prefix_map), "/opt/app/web");
+        assert_eq!(remap_path("/srv/other", &prefix_map), "/data/other");
+        assert_eq!(remap_path("/home/me", &prefix_map), "/home/me");
+    }
+
+    #[test]
+    fn keeps_spaces_in_paths() {
+        let prefix_map = map(&[("/srv/my app", "/opt/my app")]);
+        assert_eq!(
+            remap_path("/srv/my app/web server", &prefix_map),
+            "/opt/my app/web server"
+        );
+    }
+
+    #[tokio::test]
+    async fn validates_against_the_remapped_directory() {
+        let dir = std::env::temp_dir().join(format!("fpm-import-{}", std::process::id()));
+        let (old, new) = (dir.join("old app"), dir.join("new app"));
+        fs::create_dir_all(new.join("secrets")).unwrap();
+        let secret = new.join("secrets/db");
+        fs::write(&secret, "hunter2\n").unwrap();
+        fs::set_permissions(&secret, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let ecosystem = Ecosystem {
+            title: "imported".to_string(),
+            start: StartCommand::Argv(vec!["sleep".to_string(), "1".to_string()]),
+            env_secrets: Some(BTreeMap::from([(
+                "DB_PASS".to_string(),
+                "secrets/db".to_string(),
+            )])),
+            ..Default::default()
+        };
+        let backup = Backup {
+            max_id: 1,
+            process: vec![BackupProcess::new(
+                0,
+                0,
+                ecosystem,
+                old.to_string_lossy().to_string(),
+                true,
+            )],
+        };
+        let dump = dir.join("dump.toml");
+        fs::write(&dump, backup.to_toml().unwrap()).unwrap();
+        let request = |path_prefix_map: Vec<String>| {
+            json!({ "path": dump, "path_prefix_map": path_prefix_map }).to_string()
+        };
+
+        // The secret is only there on the new machine
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let answer = import(app_state.clone(), request(vec![]))
+            .await
+            .unwrap()
+            .to_string();
+        assert!(answer.contains("imported: directory"), "{answer}");
+        assert!(answer.contains("secret DB_PASS"), "{answer}");
+        assert!(app_state.lock().await.processes.is_empty());
+
+        let mapping = format!("{}={}", old.display(), new.display());
+        let answer = import(app_state.clone(), request(vec![mapping]))
+            .await
+            .unwrap()
+            .to_string();
+        assert!(answer.starts_with("success"), "{answer}");
+        let processes = app_state.lock().await.find_processes("imported");
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].path, new.to_string_lossy());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
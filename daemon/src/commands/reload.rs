@@ -0,0 +1,71 @@
+use std::{error::Error, sync::Arc};
+
+use tokio::sync::Mutex;
+
+use crate::{
+    AppState,
+    commands::delete::delete_process,
+    dependencies,
+    processes::{Ecosystem, Process},
+    socket::Response,
+};
+
+/// Replaces each instance with one running the new ecosystem, one instance at a time. The old
+/// child is only stopped once its replacement passes the readiness check, and the first
+/// replacement that doesn't stops the whole reload. Dependents follow like after a restart.
+pub async fn reload(
+    app_state: Arc<Mutex<AppState>>,
+    arg: String,
+) -> Result<Response, Box<dyn Error>> {
+    let processes = app_state.lock().await.find_processes(arg.trim());
+    let Some(first) = processes.first() else {
+        return Ok(Response::Error("Couldn't find the process"));
+    };
+    let ecosystem = match Ecosystem::reread(first).await {
+        Ok(ecosystem) => ecosystem,
+        Err(_) => return Ok(Response::Error("Couldn't get new ecosystem file")),
+    };
+    if let Err(err) = ecosystem.validate(&first.path) {
+        return Ok(Response::ErrorData(err));
+    }
+
+    let total = processes.len();
+    for (done, old) in processes.into_iter().enumerate() {
+        if let Err(err) = replace(app_state.clone(), &old, ecosystem.clone()).await {
+            return Ok(Response::ErrorData(format!(
+                "Reload of {} failed, {err}. {done} of {total} instances were replaced, \
+                 the rest keep running the old version",
+                old.name()
+            )));
+        }
+    }
+    Ok(Response::Successfully(Some(
+        "Reloaded with a new ecosystem file. Don't forget to make a backup:\n\tfpm backup",
+    )))
+}
+
+async fn replace(
+    app_state: Arc<Mutex<AppState>>,
+    old: &Arc<Process>,
+    ecosystem: Ecosystem,
+) -> Result<(), String> {
+    let stopped = old.state.lock().await.should_stop;
+    let new = app_state
+        .lock()
+        .await
+        .insert_process(ecosystem, old.path.clone(), old.instance);
+    // Stopped by the user, it only gets the new ecosystem for the next start
+    if stopped {
+        new.state.lock().await.should_stop = true;
+        delete_process(app_state, old.clone()).await;
+        return Ok(());
+    }
+    new.start().await;
+    if let Err(err) = new.wait_ready().await {
+        delete_process(app_state, new).await;
+        return Err(err);
+    }
+    delete_process(app_state, old.clone()).await;
+    dependencies::restarted(new.id);
+    Ok(())
+}
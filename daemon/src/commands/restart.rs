@@ -16,39 +16,44 @@ pub async fn restart(
     app_state: Arc<Mutex<AppState>>,
     arg: String,
 ) -> Result<Response, Box<dyn Error>> {
-    let process = if let Ok(index) = arg.parse::<u32>() {
-        AppState::find_process_by_id(app_state.clone(), index).await
-    } else {
-        AppState::find_process_by_title(app_state.clone(), arg).await
-    };
-    if let Some(process) = process {
-        restart_process(app_state, process).await
-    } else {
+    let processes = app_state.lock().await.find_processes(&arg);
+    if processes.is_empty() {
         Ok(Response::Error("Couldn't find the process"))
+    } else {
+        restart_processes(app_state, processes).await
     }
 }
 
-async fn restart_process(
+/// Instances keep their number, `fpm scale` is what changes how many there are.
+async fn restart_processes(
     app_state: Arc<Mutex<AppState>>,
-    process: Arc<Process>,
+    processes: Vec<Arc<Process>>,
 ) -> Result<Response, Box<dyn Error>> {
-    let new_ecosystem = match Ecosystem::from_path(PathBuf::from(process.path.clone())).await {
-        Ok(new_ecosystem) => new_ecosystem,
-        Err(_) => return Ok(Response::Error("Couldn't get new ecosystem file")),
-    };
-    process.stop().await;
-    let process = Arc::new(Process::init(
-        process.id,
-        new_ecosystem,
-        process.path.clone(),
-    ));
-    let mut app_state_guard = app_state.lock().await;
-    app_state_guard
-        .processes
-        .insert(process.id, process.clone());
-    tokio::spawn(async move {
-        let _ = process.run();
-    });
+    for process in processes {
+        let new_ecosystem = match Ecosystem::reread(&process).await {
+            Ok(new_ecosystem) => new_ecosystem,
+            Err(_) => return Ok(Response::Error("Couldn't get new ecosystem file")),
+        };
+        if let Err(err) = new_ecosystem.validate(&process.path) {
+            return Ok(Response::ErrorData(err));
+        }
+        process.delete().await;
+        // The spawn counts the restart and tells the dependents
+        let state = process.state.lock().await.carried_over();
+        let process = Process::new(
+            process.id,
+            process.instance,
+            new_ecosystem,
+            process.path.clone(),
+            state,
+        );
+        app_state
+            .lock()
+            .await
+            .processes
+            .insert(process.id, process.clone());
+        process.start().await;
+    }
     Ok(Response::Successfully(Some(
         "Rebooted with a new ecosystem file. Don't forget to make a backup:\n\tfpm backup",
     )))
@@ -0,0 +1,83 @@
+use std::{error::Error, sync::Arc};
+
+use serde::Deserialize;
+use tokio::{fs, sync::Mutex};
+
+use crate::{
+    AppState,
+    processes::{Ecosystem, LogsMode, ProcessKind, StartCommand},
+    project_dir,
+    socket::Response,
+};
+
+/// What `fpm run` sends, as JSON so the command keeps its arguments intact.
+#[derive(Deserialize)]
+struct RunRequest {
+    name: String,
+    /// The directory the task runs in
+    path: String,
+    command: Vec<String>,
+    timeout: Option<u64>,
+    retries: Option<u32>,
+}
+
+/// Creates a one-off task without an ecosystem file and puts it in the task queue. Its output
+/// goes to `<data dir>/logs/<name>.log`.
+pub async fn run(app_state: Arc<Mutex<AppState>>, arg: String) -> Result<Response, Box<dyn Error>> {
+    let request: RunRequest = match serde_json::from_str(&arg) {
+        Ok(request) => request,
+        Err(_) => return Ok(Response::Error("Bad usage: run <name> -- <command>")),
+    };
+    if request.command.is_empty() {
+        return Ok(Response::Error("There is no command to run"));
+    }
+    if request.name.is_empty() || request.name.contains(['/', ':']) {
+        return Ok(Response::Error(
+            "The task name can't be empty or contain / or :",
+        ));
+    }
+    if !app_state
+        .lock()
+        .await
+        .find_processes(&request.name)
+        .is_empty()
+    {
+        return Ok(Response::ErrorData(format!(
+            "A process named {0} already exists, run it again with: fpm start {0}",
+            request.name
+        )));
+    }
+
+    let logs = match project_dir() {
+        Some(project_dir) => {
+            let logs = project_dir.data_dir().join("logs");
+            fs::create_dir_all(&logs).await?;
+            Some(
+                logs.join(format!("{}.log", request.name))
+                    .to_string_lossy()
+                    .to_string(),
+            )
+        }
+        None => None,
+    };
+    let ecosystem = Ecosystem {
+        title: request.name.clone(),
+        start: StartCommand::Argv(request.command),
+        logs,
+        logs_mode: Some(LogsMode::Append),
+        kind: Some(ProcessKind::Task),
+        timeout: request.timeout,
+        retries: request.retries,
+        adhoc: Some(true),
+        ..Default::default()
+    };
+    let process = app_state
+        .lock()
+        .await
+        .insert_process(ecosystem, request.path, 0);
+    process.start().await;
+    Ok(Response::Data(format!(
+        "Task {} queued as process {}, follow it with: fpm describe {}",
+        request.name, process.id, request.name
+    )))
+}
@@ -0,0 +1,63 @@
+use std::{error::Error, sync::Arc};
+
+use tokio::sync::Mutex;
+
+use crate::{AppState, commands::delete::delete_process, socket::Response};
+
+/// Changes how many instances of a process run. New instances copy the ecosystem of the
+/// existing ones, and the highest numbered instances are the ones removed.
+pub async fn scale(
+    app_state: Arc<Mutex<AppState>>,
+    arg: String,
+) -> Result<Response, Box<dyn Error>> {
+    let mut args = arg.split_whitespace();
+    let (Some(title), Some(count), None) = (args.next(), args.next(), args.next()) else {
+        return Ok(Response::Error("Usage: scale <title> <instances>"));
+    };
+    let count = match count.parse::<u32>() {
+        Ok(0) => {
+            return Ok(Response::Error(
+                "Can't scale to 0 instances, use delete instead",
+            ));
+        }
+        Ok(count) => count,
+        Err(_) => return Ok(Response::Error("The number of instances must be a number")),
+    };
+    let processes = app_state.lock().await.find_processes(title);
+    let Some(first) = processes.first().cloned() else {
+        return Ok(Response::Error("Couldn't find the process"));
+    };
+    if processes
+        .iter()
+        .any(|process| process.ecosystem.title != title)
+    {
+        return Ok(Response::Error(
+            "Scale takes a title, not an id or a single instance",
+        ));
+    }
+
+    let current = processes.len() as u32;
+    if count > current {
+        let next = processes
+            .iter()
+            .map(|process| process.instance)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        for instance in next..next + (count - current) {
+            let process = app_state.lock().await.insert_process(
+                first.ecosystem.clone(),
+                first.path.clone(),
+                instance,
+            );
+            process.start().await;
+        }
+    } else {
+        for process in processes.into_iter().skip(count as usize) {
+            delete_process(app_state.clone(), process).await;
+        }
+    }
+    Ok(Response::Successfully(Some(
+        "Scaled. Don't forget to make a backup:\n\tfpm backup",
+    )))
+}
@@ -4,10 +4,14 @@ use std::{
     sync::Arc,
 };
 
+use chrono::Local;
+use serde::Deserialize;
 use tokio::{fs, sync::Mutex};
 
 use crate::{
     AppState,
+    commands::delete::delete_process,
+    dependencies,
     processes::{ECOSYSTEM_NAME, Ecosystem, Process, ProcessState, ProcessStatus},
     socket::Response,
 };
@@ -16,27 +20,38 @@ pub async fn start(
     app_state: Arc<Mutex<AppState>>,
     arg: String,
 ) -> Result<Response, Box<dyn Error>> {
-    let process = if let Ok(index) = arg.parse::<u32>() {
-        AppState::find_process_by_id(app_state.clone(), index).await
-    } else {
-        let by_title = AppState::find_process_by_title(app_state.clone(), arg.clone()).await;
-        if let None = by_title {
-            AppState::find_process_by_path(app_state.clone(), arg.clone()).await
+    if arg.starts_with('{') {
+        return start_adhoc(app_state, arg).await;
+    }
+    let processes = {
+        let app_state_guard = app_state.lock().await;
+        let by_target = app_state_guard.find_processes(&arg);
+        if by_target.is_empty() {
+            let mut by_path: Vec<Arc<Process>> = app_state_guard
+                .processes
+                .values()
+                .filter(|process| process.path == arg)
+                .cloned()
+                .collect();
+            by_path.sort_by_key(|process| process.instance);
+            by_path
         } else {
-            by_title
+            by_target
         }
     };
-    if let Some(process) = process {
-        if !process.exists().await {
-            tokio::spawn(async move {
-                process.run().await;
-            });
-            Ok(Response::Successfully(Some("Process started")))
-        } else {
-            Ok(Response::Error("Process already started"))
-        }
-    } else {
-        start_new_process(app_state, arg).await
+    if processes.is_empty() {
+        return start_new_process(app_state, arg).await;
+    }
+    if processes.iter().all(|process| process.exists()) {
+        return Ok(Response::Error("Process already started"));
+    }
+    let stopped: Vec<Arc<Process>> = processes
+        .into_iter()
+        .filter(|process| !process.exists())
+        .collect();
+    match dependencies::start_in_order(app_state, stopped, true).await {
+        Ok(()) => Ok(Response::Successfully(Some("Process started"))),
+        Err(err) => Ok(Response::ErrorData(err)),
     }
 }
 
@@ -48,16 +63,141 @@ pub async fn start_new_process(
         Ok(ecosystem) => ecosystem,
         Err(_) => return Ok(Response::Error("The ecosystem file could not be found")),
     };
-    let process = {
+    create_processes(app_state, ecosystem, arg).await
+}
+
+/// What `fpm start -- <command>` sends: an ecosystem put together from its arguments.
+#[derive(Deserialize)]
+struct AdhocRequest {
+    path: String,
+    ecosystem: Ecosystem,
+}
+
+async fn start_adhoc(
+    app_state: Arc<Mutex<AppState>>,
+    arg: String,
+) -> Result<Response, Box<dyn Error>> {
+    let request: AdhocRequest = match serde_json::from_str(&arg) {
+        Ok(request) => request,
+        Err(err) => return Ok(Response::ErrorData(format!("Invalid process: {err}"))),
+    };
+    let mut ecosystem = request.ecosystem;
+    if ecosystem.title.is_empty() || ecosystem.title.contains(['/', ':']) {
+        return Ok(Response::Error(
+            "The process name can't be empty or contain / or :",
+        ));
+    }
+    if !app_state
+        .lock()
+        .await
+        .find_processes(&ecosystem.title)
+        .is_empty()
+    {
+        return Ok(Response::ErrorData(format!(
+            "A process named {} already exists",
+            ecosystem.title
+        )));
+    }
+    ecosystem.adhoc = Some(true);
+    create_processes(app_state, ecosystem, request.path).await
+}
+
+async fn create_processes(
+    app_state: Arc<Mutex<AppState>>,
+    ecosystem: Ecosystem,
+    path: String,
+) -> Result<Response, Box<dyn Error>> {
+    let instances = match ecosystem.instance_count() {
+        Ok(instances) => instances,
+        Err(err) => return Ok(Response::ErrorData(err)),
+    };
+    if let Err(err) = ecosystem.validate(&path) {
+        return Ok(Response::ErrorData(err));
+    }
+    let mut processes = Vec::with_capacity(instances as usize);
+    {
         let mut app_state_guard = app_state.lock().await;
-        let id = app_state_guard.id;
-        app_state_guard.id += 1;
-        let process = Arc::new(Process::init(id, ecosystem, arg));
-        app_state_guard.processes.insert(id, process.clone());
-        process
+        for instance in 0..instances {
+            processes.push(app_state_guard.insert_process(
+                ecosystem.clone(),
+                path.clone(),
+                instance,
+            ));
+        }
+    }
+
+    let all: Vec<Arc<Process>> = app_state.lock().await.processes.values().cloned().collect();
+    let missing = dependencies::missing(&processes[0], &all);
+    let problem = if !missing.is_empty() {
+        Some(format!(
+            "{} depends on {}, start it first",
+            ecosystem.title,
+            missing.join(", ")
+        ))
+    } else {
+        dependencies::order(&all).err()
     };
-    tokio::spawn(async move {
-        process.run().await;
-    });
-    Ok(Response::Successfully(Some("Process started")))
+    if let Some(problem) = problem {
+        for process in processes {
+            delete_process(app_state.clone(), process).await;
+        }
+        return Ok(Response::ErrorData(problem));
+    }
+
+    // A new job waits for its schedule, `fpm start` on it afterwards runs it right away
+    if ecosystem.is_job() {
+        let next_run = ecosystem
+            .next_run()
+            .map(|next_run| {
+                next_run
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|| "never".to_string());
+        return Ok(Response::Data(format!(
+            "Job scheduled, next run at {next_run}"
+        )));
+    }
+
+    match dependencies::start_in_order(app_state, processes, true).await {
+        Ok(()) => Ok(Response::Successfully(Some("Process started"))),
+        Err(err) => Ok(Response::ErrorData(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::io::AsyncWriteExt;
+
+    use super::AdhocRequest;
+    use crate::{processes::StartCommand, socket};
+
+    #[tokio::test]
+    async fn reads_adhoc_requests_over_1_kib() {
+        let argument = "x".repeat(4096);
+        let request = json!({
+            "path": "/srv/app",
+            "ecosystem": { "title": "long", "start": ["echo", argument] },
+        });
+        let message = format!("start {request}");
+        assert!(message.len() > 1024);
+
+        let (mut client, mut daemon) = tokio::io::duplex(512);
+        let send = tokio::spawn(async move {
+            client.write_all(message.as_bytes()).await.unwrap();
+            client.shutdown().await.unwrap();
+        });
+        let received = socket::read_message(&mut daemon).await.unwrap();
+        send.await.unwrap();
+
+        let arg = received.strip_prefix("start ").unwrap();
+        let request: AdhocRequest = serde_json::from_str(arg).unwrap();
+        assert_eq!(request.ecosystem.title, "long");
+        match request.ecosystem.start {
+            StartCommand::Argv(argv) => assert_eq!(argv, ["echo", argument.as_str()]),
+            StartCommand::Shell(_) => panic!("the argv became a shell command"),
+        }
+    }
 }
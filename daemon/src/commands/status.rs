@@ -4,10 +4,12 @@ use std::{
     sync::Arc,
 };
 
+use chrono::Local;
 use tokio::{fs, sync::Mutex};
 
 use crate::{
     AppState,
+    commands::describe,
     processes::{ECOSYSTEM_NAME, Ecosystem, Process, ProcessState, ProcessStatus},
     socket::Response,
 };
@@ -19,26 +21,67 @@ pub async fn status(
     if arg.is_empty() {
         Ok(Response::Data(all_status(app_state).await))
     } else {
-        Ok(Response::Successfully(Some("WIP! Contribute if you want!")))
+        describe(app_state, arg).await
     }
 }
 
 pub async fn all_status(app_state: Arc<Mutex<AppState>>) -> String {
-    let processes: Vec<Arc<Process>> = app_state
+    let mut processes: Vec<Arc<Process>> = app_state
         .lock()
         .await
         .processes
         .clone()
         .into_values()
         .collect();
+    processes.sort_by_key(|process| process.id);
     let mut output = String::from("Status of processes:\n");
     for process in processes {
-        let process_status = process.state.lock().await.status.clone();
-        output += format!(
-            "{}. {} - {}\n",
-            process.id, process.ecosystem.title, process_status
-        )
-        .as_str();
+        let process_status = process.status();
+        let state = process.state.lock().await;
+        output += format!("{}. {} - {}", process.id, process.name(), process_status).as_str();
+        if let Some(uptime) = state.uptime(process_status) {
+            output += format!(", up {}", format_duration(uptime)).as_str();
+        }
+        if state.restarts > 0 {
+            output += format!(", {} restarts", state.restarts).as_str();
+        }
+        if let Some(last_exit) = state.last_exit {
+            output += format!(", last exit: {}", last_exit).as_str();
+        }
+        if process.ecosystem.is_job()
+            && !state.should_stop
+            && let Some(next_run) = process.ecosystem.next_run()
+        {
+            output += format!(
+                ", next run at {}",
+                next_run.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+            )
+            .as_str();
+        }
+        if !state.descendants.is_empty() {
+            output += format!(" ({} descendant processes)", state.descendants.len()).as_str();
+        }
+        output += "\n";
     }
     output
 }
+
+/// Formats a duration the short way, like `2d 3h` or `4m 10s`.
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let seconds = duration.num_seconds().max(0);
+    let (days, hours, minutes, seconds) = (
+        seconds / 86400,
+        seconds % 86400 / 3600,
+        seconds % 3600 / 60,
+        seconds % 60,
+    );
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m {seconds}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
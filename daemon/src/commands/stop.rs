@@ -4,7 +4,7 @@ use std::{
     sync::Arc,
 };
 
-use tokio::{fs, sync::Mutex};
+use tokio::{fs, sync::Mutex, task::JoinSet};
 
 use crate::{
     AppState,
@@ -16,19 +16,20 @@ pub async fn stop(
     app_state: Arc<Mutex<AppState>>,
     arg: String,
 ) -> Result<Response, Box<dyn Error>> {
-    let process = if let Ok(index) = arg.parse::<u32>() {
-        AppState::find_process_by_id(app_state.clone(), index).await
-    } else {
-        AppState::find_process_by_title(app_state.clone(), arg).await
-    };
-    if let Some(process) = process {
-        stop_process(process).await
-    } else {
+    let processes = app_state.lock().await.find_processes(&arg);
+    if processes.is_empty() {
         Ok(Response::Error("Couldn't find the process"))
+    } else {
+        stop_processes(processes).await
     }
 }
 
-async fn stop_process(process: Arc<Process>) -> Result<Response, Box<dyn Error>> {
-    process.stop().await;
+/// Instances are stopped side by side, so their stop timeouts don't add up.
+async fn stop_processes(processes: Vec<Arc<Process>>) -> Result<Response, Box<dyn Error>> {
+    let mut stopping = JoinSet::new();
+    for process in processes {
+        stopping.spawn(async move { process.stop().await });
+    }
+    stopping.join_all().await;
     Ok(Response::Successfully(Some("The process is stopped")))
 }
@@ -0,0 +1,21 @@
+use std::{error::Error, sync::Arc, time::Duration};
+
+use tokio::sync::Mutex;
+
+use crate::{AppState, socket::Response, upgrade};
+
+pub async fn upgrade(
+    app_state: Arc<Mutex<AppState>>,
+    _arg: String,
+) -> Result<Response, Box<dyn Error>> {
+    // Give the connection a moment to deliver the answer before the daemon image is replaced
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        if let Err(err) = upgrade::exec_upgrade(app_state).await {
+            eprintln!("Upgrade failed, keeping the running daemon: {err}");
+        }
+    });
+    Ok(Response::Successfully(Some(
+        "The daemon is re-executing itself, managed processes keep running",
+    )))
+}
@@ -0,0 +1,66 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::Deserialize;
+use tokio::fs;
+
+use crate::{access::Allow, api::Api, processes, project_dir, remote::Tcp};
+
+pub const CONFIG_NAME: &str = "config.toml";
+
+/// Daemon settings, read once at startup from `config.toml` in the config directory.
+/// Every field is optional, a missing file means the defaults.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// Seconds the daemon gets to stop every process on SIGTERM/SIGINT before the rest is killed
+    pub shutdown_timeout: u64,
+    /// How many tasks may run at the same time, the rest wait in the queue
+    pub task_concurrency: usize,
+    /// Where the socket is created, `$XDG_RUNTIME_DIR/fpm.sock` by default, `/run/fpm/fpm.sock`
+    /// without it, or `/tmp/fpm-<uid>/fpm.sock` when not running as root
+    pub socket: Option<PathBuf>,
+    /// Who besides the daemon's own user may send a command, by command name or `"*"` for
+    /// all of them. They need to reach the socket too, so this goes with a `socket` outside
+    /// the private `$XDG_RUNTIME_DIR`.
+    ///
+    /// Processes always run as the daemon's user or the `user` of their ecosystem, never as
+    /// whoever started them. Allowing `start`, `run`, `import` or `reload` to someone lets
+    /// them run anything they like as the daemon's user, root when fpmd runs as root, so
+    /// only list users who may do that anyway
+    pub access: HashMap<String, Allow>,
+    /// A TCP listener with TLS and tokens for `fpm --host`, off unless set
+    pub tcp: Option<Tcp>,
+    /// The HTTP/JSON API on a loopback port or a socket, off unless set
+    pub api: Option<Api>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            shutdown_timeout: 30,
+            task_concurrency: processes::DEFAULT_TASK_CONCURRENCY,
+            socket: None,
+            access: HashMap::new(),
+            tcp: None,
+            api: None,
+        }
+    }
+}
+
+pub async fn load_config() -> Config {
+    let config_path = match project_dir() {
+        Some(project_dir) => project_dir.config_dir().join(CONFIG_NAME),
+        None => return Config::default(),
+    };
+    let content = match fs::read_to_string(&config_path).await {
+        Ok(content) => content,
+        Err(_) => return Config::default(),
+    };
+    match toml::from_str(&content) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Invalid {}, using defaults: {}", config_path.display(), err);
+            Config::default()
+        }
+    }
+}
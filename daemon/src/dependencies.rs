@@ -0,0 +1,283 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, LazyLock},
+};
+
+use tokio::sync::{Mutex, broadcast};
+
+use crate::{AppState, processes::Process};
+
+/// Ids of processes whose child was started again, picked up by [`propagate_restarts`].
+static RESTARTED: LazyLock<broadcast::Sender<u32>> = LazyLock::new(|| broadcast::channel(64).0);
+
+pub fn restarted(id: u32) {
+    let _ = RESTARTED.send(id);
+}
+
+fn dependencies(process: &Process) -> &[String] {
+    process.ecosystem.depends_on.as_deref().unwrap_or_default()
+}
+
+fn by_title(processes: Vec<Arc<Process>>) -> HashMap<String, Vec<Arc<Process>>> {
+    let mut by_title: HashMap<String, Vec<Arc<Process>>> = HashMap::new();
+    for process in processes {
+        by_title
+            .entry(process.ecosystem.title.clone())
+            .or_default()
+            .push(process);
+    }
+    for instances in by_title.values_mut() {
+        instances.sort_by_key(|process| process.instance);
+    }
+    by_title
+}
+
+/// Titles ordered so that every process comes after what it depends on. Dependencies that
+/// aren't managed are skipped, a cycle is an error.
+pub fn order(processes: &[Arc<Process>]) -> Result<Vec<String>, String> {
+    let mut graph: HashMap<&str, &[String]> = HashMap::new();
+    for process in processes {
+        graph
+            .entry(process.ecosystem.title.as_str())
+            .or_insert(dependencies(process));
+    }
+    sort(&graph)
+}
+
+/// Titles of `graph`, every one after the titles it points to.
+fn sort(graph: &HashMap<&str, &[String]>) -> Result<Vec<String>, String> {
+    let mut titles: Vec<&str> = graph.keys().copied().collect();
+    titles.sort();
+
+    let mut ordered = vec![];
+    let mut done = HashSet::new();
+    for title in titles {
+        visit(title, graph, &mut vec![], &mut done, &mut ordered)?;
+    }
+    Ok(ordered)
+}
+
+fn visit<'a>(
+    title: &'a str,
+    graph: &HashMap<&'a str, &'a [String]>,
+    path: &mut Vec<&'a str>,
+    done: &mut HashSet<&'a str>,
+    ordered: &mut Vec<String>,
+) -> Result<(), String> {
+    if done.contains(title) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|visited| *visited == title) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(title);
+        return Err(format!("dependency cycle: {}", cycle.join(" -> ")));
+    }
+    path.push(title);
+    for dependency in graph[title] {
+        if let Some((dependency, _)) = graph.get_key_value(dependency.as_str()) {
+            visit(dependency, graph, path, done, ordered)?;
+        }
+    }
+    path.pop();
+    done.insert(title);
+    ordered.push(title.to_string());
+    Ok(())
+}
+
+/// Dependencies of `process` that no managed process provides.
+pub fn missing(process: &Process, processes: &[Arc<Process>]) -> Vec<String> {
+    dependencies(process)
+        .iter()
+        .filter(|dependency| {
+            !processes
+                .iter()
+                .any(|process| process.ecosystem.title == **dependency)
+        })
+        .cloned()
+        .collect()
+}
+
+async fn wait_ready(instances: &[Arc<Process>]) {
+    for process in instances {
+        if process.state.lock().await.should_stop {
+            eprintln!("{} is stopped, not waiting for it", process.name());
+            continue;
+        }
+        if let Err(err) = process.wait_ready().await {
+            eprintln!(
+                "{} isn't ready, {err}. Starting what depends on it anyway",
+                process.name()
+            );
+        }
+    }
+}
+
+/// Starts `targets` after what they depend on, waiting for every dependency to pass its
+/// readiness check first. With `pull_in`, dependencies that aren't running are started too.
+pub async fn start_in_order(
+    app_state: Arc<Mutex<AppState>>,
+    targets: Vec<Arc<Process>>,
+    pull_in: bool,
+) -> Result<(), String> {
+    let all: Vec<Arc<Process>> = app_state.lock().await.processes.values().cloned().collect();
+    let order = order(&all)?;
+    let all = by_title(all);
+
+    let mut wanted = by_title(targets);
+    if pull_in {
+        let mut queue: Vec<String> = wanted.keys().cloned().collect();
+        while let Some(title) = queue.pop() {
+            let Some(process) = all.get(&title).and_then(|instances| instances.first()) else {
+                continue;
+            };
+            for dependency in dependencies(process) {
+                if !wanted.contains_key(dependency)
+                    && let Some(instances) = all.get(dependency)
+                {
+                    wanted.insert(dependency.clone(), instances.clone());
+                    queue.push(dependency.clone());
+                }
+            }
+        }
+    }
+
+    let mut ready: HashSet<&str> = HashSet::new();
+    let mut started: HashSet<&str> = HashSet::new();
+    for title in &order {
+        let Some(instances) = wanted.get(title) else {
+            continue;
+        };
+        for dependency in dependencies(&instances[0]) {
+            // A dependency that isn't running and wasn't started here, or a job that only
+            // runs on its schedule, would only be waited on until the timeout
+            if let Some(dependency) = all.get(dependency)
+                && !dependency[0].ecosystem.is_job()
+                && (started.contains(dependency[0].ecosystem.title.as_str())
+                    || dependency.iter().any(|process| process.exists()))
+                && ready.insert(dependency[0].ecosystem.title.as_str())
+            {
+                wait_ready(dependency).await;
+            }
+        }
+        for process in instances {
+            if !process.exists() {
+                process.start().await;
+                started.insert(title.as_str());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Restarts the dependents that set `restart_with_dependencies` whenever one of their
+/// dependencies comes back, once it is ready again.
+pub async fn propagate_restarts(app_state: Arc<Mutex<AppState>>) {
+    let mut restarted = RESTARTED.subscribe();
+    loop {
+        let id = match restarted.recv().await {
+            Ok(id) => id,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let all: Vec<Arc<Process>> = app_state.lock().await.processes.values().cloned().collect();
+        // Restarts would go round in circles forever
+        if order(&all).is_err() {
+            continue;
+        }
+        let Some(process) = all.iter().find(|process| process.id == id).cloned() else {
+            continue;
+        };
+        let dependents: Vec<Arc<Process>> = all
+            .into_iter()
+            .filter(|dependent| dependent.ecosystem.restart_with_dependencies == Some(true))
+            .filter(|dependent| dependencies(dependent).contains(&process.ecosystem.title))
+            .collect();
+        if dependents.is_empty() {
+            continue;
+        }
+        tokio::spawn(async move {
+            if process.wait_ready().await.is_err() {
+                return;
+            }
+            for dependent in dependents {
+                if dependent.exists() {
+                    println!(
+                        "Restarting {} after {} restarted",
+                        dependent.name(),
+                        process.name()
+                    );
+                    dependent.restart().await;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::sort;
+
+    fn graph<'a>(edges: &'a [(&'a str, Vec<String>)]) -> HashMap<&'a str, &'a [String]> {
+        edges
+            .iter()
+            .map(|(title, dependencies)| (*title, dependencies.as_slice()))
+            .collect()
+    }
+
+    fn deps(titles: &[&str]) -> Vec<String> {
+        titles.iter().map(|title| title.to_string()).collect()
+    }
+
+    #[test]
+    fn orders_a_chain() {
+        let edges = [
+            ("web", deps(&["api"])),
+            ("api", deps(&["db"])),
+            ("db", deps(&[])),
+        ];
+        assert_eq!(sort(&graph(&edges)).unwrap(), ["db", "api", "web"]);
+    }
+
+    #[test]
+    fn orders_a_diamond() {
+        let edges = [
+            ("web", deps(&["api", "worker"])),
+            ("api", deps(&["db"])),
+            ("worker", deps(&["db"])),
+            ("db", deps(&[])),
+        ];
+        let order = sort(&graph(&edges)).unwrap();
+        let position = |title: &str| order.iter().position(|ordered| ordered == title).unwrap();
+        assert_eq!(order.len(), 4);
+        assert_eq!(position("db"), 0);
+        assert!(position("api") < position("web"));
+        assert!(position("worker") < position("web"));
+    }
+
+    #[test]
+    fn finds_cycles() {
+        let edges = [("api", deps(&["api"]))];
+        assert_eq!(
+            sort(&graph(&edges)).unwrap_err(),
+            "dependency cycle: api -> api"
+        );
+
+        let edges = [
+            ("a", deps(&["b"])),
+            ("b", deps(&["c"])),
+            ("c", deps(&["a"])),
+        ];
+        assert_eq!(
+            sort(&graph(&edges)).unwrap_err(),
+            "dependency cycle: a -> b -> c -> a"
+        );
+    }
+
+    #[test]
+    fn skips_unknown_dependencies() {
+        let edges = [("api", deps(&["redis"])), ("web", deps(&["api"]))];
+        assert_eq!(sort(&graph(&edges)).unwrap(), ["api", "web"]);
+    }
+}
@@ -3,10 +3,22 @@ use std::{borrow::Cow, collections::HashMap, error::Error, sync::Arc};
 use directories::ProjectDirs;
 use tokio::sync::Mutex;
 
+mod access;
+mod api;
+mod audit;
 mod backup;
+mod cgroups;
+mod children;
 mod commands;
+mod config;
+mod dependencies;
+mod metrics;
 mod processes;
+mod reaper;
+mod remote;
+mod shutdown;
 mod socket;
+mod upgrade;
 
 #[derive(Debug, Clone)]
 pub struct AppState {
@@ -23,59 +35,41 @@ impl AppState {
         Self::new(0, HashMap::new())
     }
 
-    pub async fn find_process_by_id(
-        // need to improve this peace of shitcode
-        this: Arc<Mutex<Self>>,
-        id: u32,
-    ) -> Option<Arc<processes::Process>> {
-        let app_state_guard = this.lock().await;
-        app_state_guard.processes.get(&id).cloned()
-    }
-
-    pub async fn find_process_by_title(
-        this: Arc<Mutex<Self>>,
-        title: String,
-    ) -> Option<Arc<processes::Process>> {
-        // todo: dry pattern broken :(. Refactor
-        let process = {
-            let guard = this.lock().await;
-            let processes: Vec<Arc<processes::Process>> =
-                guard.processes.clone().into_values().collect();
-            let mut id: Option<u32> = None;
-            for process in processes.into_iter() {
-                if process.ecosystem.title == title {
-                    id = Some(process.id);
-                }
-            }
-            match id {
-                Some(id) => guard.processes.get(&id).cloned(),
-                None => None,
-            }
-        };
+    pub fn insert_process(
+        &mut self,
+        ecosystem: processes::Ecosystem,
+        path: String,
+        instance: u32,
+    ) -> Arc<processes::Process> {
+        let id = self.id;
+        self.id += 1;
+        let process = processes::Process::init(id, instance, ecosystem, path);
+        self.processes.insert(id, process.clone());
         process
     }
 
-    pub async fn find_process_by_path(
-        // todo: dry pattern broken :(. Refactor
-        this: Arc<Mutex<Self>>,
-        path: String,
-    ) -> Option<Arc<processes::Process>> {
-        let process = {
-            let guard = this.lock().await;
-            let processes: Vec<Arc<processes::Process>> =
-                guard.processes.clone().into_values().collect();
-            let mut id: Option<u32> = None;
-            for process in processes.into_iter() {
-                if process.path == path {
-                    id = Some(process.id);
-                }
-            }
-            match id {
-                Some(id) => guard.processes.get(&id).cloned(),
-                None => None,
-            }
+    /// The processes `target` refers to: an id, one instance as `title:instance`, or a title,
+    /// which covers all of its instances. Sorted by instance.
+    pub fn find_processes(&self, target: &str) -> Vec<Arc<processes::Process>> {
+        let mut found: Vec<Arc<processes::Process>> = if let Ok(id) = target.parse::<u32>() {
+            self.processes.get(&id).cloned().into_iter().collect()
+        } else {
+            let instance = target
+                .rsplit_once(':')
+                .and_then(|(title, instance)| Some((title, instance.parse::<u32>().ok()?)));
+            let (title, instance) = match instance {
+                Some((title, instance)) => (title, Some(instance)),
+                None => (target, None),
+            };
+            self.processes
+                .values()
+                .filter(|process| process.ecosystem.title == title)
+                .filter(|process| instance.is_none_or(|instance| process.instance == instance))
+                .cloned()
+                .collect()
         };
-        process
+        found.sort_by_key(|process| (process.instance, process.id));
+        found
     }
 }
 
@@ -86,13 +80,46 @@ pub fn project_dir() -> Option<ProjectDirs> {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    reaper::become_subreaper();
+    let config = config::load_config().await;
+    processes::set_task_concurrency(config.task_concurrency);
+    cgroups::init();
+    access::init(&config);
+    let inherited = upgrade::Inherited::from_args();
+    let backup = match inherited.handoff.clone() {
+        Some(handoff) => match upgrade::load_handoff(handoff).await {
+            Some(backup) => backup,
+            None => backup::load_backup().await,
+        },
+        None => backup::load_backup().await,
+    };
     let app_state = Arc::new(Mutex::new(AppState::default()));
     let app_state_processes = app_state.clone();
-    let _ = processes::start_processes(app_state_processes).await;
+    let _ = processes::start_processes(app_state_processes, backup).await;
+    tokio::spawn(reaper::run(app_state.clone()));
+    tokio::spawn(dependencies::propagate_restarts(app_state.clone()));
+    if let Some(tcp) = config.tcp.clone() {
+        let app_state_remote = app_state.clone();
+        tokio::spawn(async move {
+            if let Err(err) = remote::start_remote(app_state_remote, tcp).await {
+                eprintln!("The TCP listener is off, {err}");
+            }
+        });
+    }
+    if let Some(api) = config.api.clone() {
+        let app_state_api = app_state.clone();
+        tokio::spawn(async move {
+            if let Err(err) = api::start_api(app_state_api, api).await {
+                eprintln!("The API is off, {err}");
+            }
+        });
+    }
     let app_state_socket = app_state.clone();
     println!("{:#?}", app_state_socket.lock().await);
-    // tokio::spawn(async move {
-    let _ = socket::start_socket(app_state_socket).await;
-    // });
+    tokio::select! {
+        result = socket::start_socket(app_state_socket, &config, inherited.listener) => result?,
+        _ = shutdown::wait_for_signal() => {}
+    }
+    shutdown::shutdown(app_state, &config).await;
     Ok(())
 }
@@ -0,0 +1,305 @@
+use std::{fmt::Write, sync::Arc};
+
+use procfs::WithCurrentSystemInfo;
+use tokio::sync::Mutex;
+
+use crate::{
+    AppState,
+    cgroups::Cgroup,
+    processes::{ExitReason, Process, ProcessStatus},
+};
+
+/// Every value of the `status` label, one series each so a dashboard sees the transitions.
+const STATUSES: [&str; 9] = [
+    "queued", "starting", "running", "stopping", "stopped", "exited", "crashed", "backoff",
+    "errored",
+];
+
+/// Name, type and help of every metric, in the order they are written.
+const METRICS: [(&str, &str, &str); 10] = [
+    ("fpm_process_up", "gauge", "Whether the process is running"),
+    (
+        "fpm_process_status",
+        "gauge",
+        "The status of the process, 1 for the current one",
+    ),
+    (
+        "fpm_process_restarts_total",
+        "counter",
+        "Restarts since the process was started",
+    ),
+    (
+        "fpm_process_last_exit_code",
+        "gauge",
+        "Exit code of the last run, 128 + the signal if it was killed",
+    ),
+    (
+        "fpm_process_uptime_seconds",
+        "gauge",
+        "Time since the current run started",
+    ),
+    (
+        "fpm_process_resident_memory_bytes",
+        "gauge",
+        "Resident memory of the process and its descendants",
+    ),
+    (
+        "fpm_process_cpu_seconds_total",
+        "counter",
+        "CPU time used in the cgroup of the process, only with cgroups",
+    ),
+    (
+        "fpm_process_cpu_seconds",
+        "gauge",
+        "User and system CPU time of the process and the descendants alive now",
+    ),
+    (
+        "fpm_process_open_fds",
+        "gauge",
+        "Open file descriptors of the process and its descendants",
+    ),
+    (
+        "fpm_process_log_bytes_total",
+        "counter",
+        "Bytes written to the log file, including what restarts truncated",
+    ),
+];
+
+/// What procfs says about a process and everything it spawned.
+#[derive(Default)]
+struct Sample {
+    rss_bytes: u64,
+    cpu_seconds: f64,
+    open_fds: usize,
+}
+
+/// Sums up `pids`, each with the start time it had when it was seen, if known. A pid that
+/// started at another time was reused by something else and doesn't count.
+fn sample(pids: impl Iterator<Item = (u32, Option<u64>)>) -> Option<Sample> {
+    let ticks = procfs::ticks_per_second() as f64;
+    let mut sample = None;
+    for (pid, start_time) in pids {
+        let Ok(process) = procfs::process::Process::new(pid as i32) else {
+            continue;
+        };
+        let Ok(stat) = process.stat() else {
+            continue;
+        };
+        if start_time.is_some_and(|start_time| start_time != stat.starttime) {
+            continue;
+        }
+        let sample = sample.get_or_insert_with(Sample::default);
+        sample.rss_bytes += stat.rss_bytes().get();
+        sample.cpu_seconds += (stat.utime + stat.stime) as f64 / ticks;
+        sample.open_fds += process.fd_count().unwrap_or(0);
+    }
+    sample
+}
+
+fn status_label(status: ProcessStatus) -> &'static str {
+    match status {
+        ProcessStatus::Queued => "queued",
+        ProcessStatus::Starting => "starting",
+        ProcessStatus::Running => "running",
+        ProcessStatus::Stopping => "stopping",
+        ProcessStatus::Stopped => "stopped",
+        ProcessStatus::Exited(_) => "exited",
+        ProcessStatus::Crashed(_) => "crashed",
+        ProcessStatus::Backoff => "backoff",
+        ProcessStatus::Errored => "errored",
+    }
+}
+
+/// Escapes a label value, a title could hold anything.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The values of one process, by metric name. A metric the process doesn't have yet, like
+/// the exit code before its first exit, is left out.
+async fn values(process: &Process) -> Vec<(&'static str, Option<String>, f64)> {
+    let status = process.status();
+    let state = process.state.lock().await;
+    let mut values = vec![(
+        "fpm_process_up",
+        None,
+        (status == ProcessStatus::Running) as u8 as f64,
+    )];
+    for label in STATUSES {
+        let current = (label == status_label(status)) as u8 as f64;
+        values.push(("fpm_process_status", Some(label.to_string()), current));
+    }
+    values.push(("fpm_process_restarts_total", None, state.restarts as f64));
+    match state.last_exit {
+        Some(ExitReason::Code(code)) => {
+            values.push(("fpm_process_last_exit_code", None, code as f64))
+        }
+        Some(ExitReason::Signal(signal)) => {
+            values.push(("fpm_process_last_exit_code", None, (128 + signal) as f64))
+        }
+        Some(ExitReason::Unknown) | None => {}
+    }
+    if let Some(uptime) = state.uptime(status) {
+        let seconds = uptime.num_milliseconds() as f64 / 1000.0;
+        values.push(("fpm_process_uptime_seconds", None, seconds));
+    }
+    let running = status == ProcessStatus::Running;
+    let log_bytes_truncated = state.log_bytes_truncated;
+    let pids: Vec<(u32, Option<u64>)> = state
+        .uid
+        .map(|pid| (pid, None))
+        .into_iter()
+        .chain(
+            state
+                .descendants
+                .iter()
+                .map(|(pid, start_time)| (*pid, Some(*start_time))),
+        )
+        .collect();
+    drop(state);
+
+    // procfs and the file system block, and the state stays free for the process meanwhile
+    let log_path = process.log_path();
+    let cgroup = Cgroup::of(process.id);
+    let (sample, cpu_usec, log_size) = tokio::task::spawn_blocking(move || {
+        let sample = if running {
+            sample(pids.into_iter())
+        } else {
+            None
+        };
+        // The kernel keeps counting in the cgroup when a descendant exits, procfs doesn't
+        let cpu_usec = cgroup.and_then(|cgroup| cgroup.usage().cpu_usec);
+        let log_size = log_path
+            .and_then(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len());
+        (sample, cpu_usec, log_size)
+    })
+    .await
+    .unwrap_or_default();
+    if let Some(sample) = sample {
+        values.push((
+            "fpm_process_resident_memory_bytes",
+            None,
+            sample.rss_bytes as f64,
+        ));
+        values.push(("fpm_process_cpu_seconds", None, sample.cpu_seconds));
+        values.push(("fpm_process_open_fds", None, sample.open_fds as f64));
+    }
+    if let Some(cpu_usec) = cpu_usec {
+        let seconds = cpu_usec as f64 / 1_000_000.0;
+        values.push(("fpm_process_cpu_seconds_total", None, seconds));
+    }
+    if let Some(log_size) = log_size {
+        let written = log_bytes_truncated + log_size;
+        values.push(("fpm_process_log_bytes_total", None, written as f64));
+    }
+    values
+}
+
+/// Every process in the Prometheus text format.
+pub async fn render(app_state: Arc<Mutex<AppState>>) -> String {
+    let mut processes: Vec<Arc<Process>> =
+        app_state.lock().await.processes.values().cloned().collect();
+    processes.sort_by_key(|process| process.id);
+    let mut samples = vec![];
+    for process in &processes {
+        let labels = format!(
+            "id=\"{}\",title=\"{}\",instance=\"{}\"",
+            process.id,
+            escape(&process.ecosystem.title),
+            process.instance
+        );
+        samples.push((labels, values(process).await));
+    }
+
+    let mut output = String::new();
+    for (name, kind, help) in METRICS {
+        let _ = writeln!(output, "# HELP {name} {help}");
+        let _ = writeln!(output, "# TYPE {name} {kind}");
+        for (labels, values) in &samples {
+            for (_, status, value) in values.iter().filter(|(metric, ..)| *metric == name) {
+                match status {
+                    Some(status) => {
+                        let _ = writeln!(output, "{name}{{{labels},status=\"{status}\"}} {value}");
+                    }
+                    None => {
+                        let _ = writeln!(output, "{name}{{{labels}}} {value}");
+                    }
+                }
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::processes::{Ecosystem, ProcessState};
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("api"), "api");
+        assert_eq!(escape(r#"say "hi""#), r#"say \"hi\""#);
+        assert_eq!(escape("a\\b\nc"), "a\\\\b\\nc");
+    }
+
+    #[tokio::test]
+    async fn renders_a_stopped_process() {
+        let dir = std::env::temp_dir().join(format!("fpm-metrics-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("out.log"), "hello\n").unwrap();
+        let ecosystem = Ecosystem {
+            title: "web \"x\"".to_string(),
+            logs: Some("out.log".to_string()),
+            ..Default::default()
+        };
+        let mut state = ProcessState::default();
+        state.restarts = 2;
+        state.last_exit = Some(ExitReason::Signal(9));
+        state.log_bytes_truncated = 100;
+        let process = Process::new(3, 0, ecosystem, dir.to_string_lossy().to_string(), state);
+        let app_state = Arc::new(Mutex::new(AppState::new(4, HashMap::from([(3, process)]))));
+
+        let output = render(app_state).await;
+        let lines: Vec<&str> = output.lines().collect();
+        let labels = r#"id="3",title="web \"x\"",instance="0""#;
+        for expected in [
+            "# HELP fpm_process_up Whether the process is running".to_string(),
+            "# TYPE fpm_process_restarts_total counter".to_string(),
+            format!("fpm_process_up{{{labels}}} 0"),
+            format!("fpm_process_status{{{labels},status=\"stopped\"}} 1"),
+            format!("fpm_process_status{{{labels},status=\"running\"}} 0"),
+            format!("fpm_process_restarts_total{{{labels}}} 2"),
+            format!("fpm_process_last_exit_code{{{labels}}} 137"),
+            format!("fpm_process_log_bytes_total{{{labels}}} 106"),
+        ] {
+            assert!(
+                lines.contains(&expected.as_str()),
+                "{expected} in\n{output}"
+            );
+        }
+        // Nothing is sampled from a process that isn't running
+        for name in [
+            "fpm_process_uptime_seconds{",
+            "fpm_process_resident_memory_bytes{",
+            "fpm_process_cpu_seconds{",
+            "fpm_process_open_fds{",
+        ] {
+            assert!(!output.contains(name), "{name} in\n{output}");
+        }
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|line| line.starts_with("# TYPE"))
+                .count(),
+            10
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,40 +1,76 @@
 use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     env,
     error::Error,
     fmt::Display,
-    path::{Path, PathBuf},
-    process::{self, Stdio},
+    os::unix::process::ExitStatusExt,
+    path::PathBuf,
+    pin::Pin,
+    process::Stdio,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use chrono::{DateTime, Utc};
-use directories::ProjectDirs;
 use nix::{
-    sys::signal::{Signal, kill},
+    sys::{
+        signal::{Signal, kill},
+        wait::WaitStatus,
+    },
     unistd::Pid,
 };
 use notify::{Event, RecursiveMode, Watcher};
-use procfs::process::all_processes;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tokio::{
     fs,
-    io::{AsyncBufReadExt, BufReader},
-    process::{Child, Command},
-    sync::{
-        Mutex,
-        broadcast::{self, Receiver, Sender},
-        mpsc,
-    },
+    io::AsyncWriteExt,
+    process::Child,
+    sync::{Mutex, OwnedSemaphorePermit, mpsc, watch},
+    task::JoinHandle,
+    time::Sleep,
 };
 
 use crate::{
-    backup::{self, BackupProcess},
-    project_dir,
+    backup::BackupProcess,
+    cgroups::Cgroup,
+    children::{self, ChildRecord},
+    dependencies,
 };
 
+mod command;
+mod credentials;
+mod limits;
+mod priority;
+mod ready;
+mod sandbox;
+mod schedule;
+mod secrets;
+mod status;
+mod task;
+pub use command::StartCommand;
+pub use limits::{CgroupLimits, Limit, Rlimits};
+pub use priority::{Cpus, IoNice};
+pub use ready::ReadyCheck;
+pub use sandbox::Sandbox;
+pub use schedule::JobRun;
+pub use secrets::REDACTED;
+pub use status::{ExitReason, ProcessCommand, ProcessEvent, ProcessStatus};
+pub use task::{DEFAULT_TASK_CONCURRENCY, set_task_concurrency};
+
 pub const ECOSYSTEM_NAME: &str = "pm-ecosystem.toml";
+/// Set in every child's environment so a restarted daemon can recognize its children
+pub const ID_ENV: &str = "FPM_ID";
+pub const INSTANCE_ENV: &str = "FPM_INSTANCE";
+pub const PORT_ENV: &str = "PORT";
+pub const DEFAULT_STOP_TIMEOUT: u64 = 3;
+/// Delay before the first restart, doubled on every crash in a row up to `BACKOFF_MAX`
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+/// A run this long counts as healthy and resets the backoff
+const BACKOFF_RESET: Duration = Duration::from_secs(30);
+const HISTORY_LIMIT: usize = 50;
 
-#[derive(Deserialize, Clone, Copy, Debug)]
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
 pub enum LogsMode {
     #[serde(rename = "override")]
     Override,
@@ -52,7 +88,7 @@ impl Display for LogsMode {
     }
 }
 
-#[derive(Deserialize, Clone, Copy, Debug)]
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum RestartMode {
     #[serde(rename = "on_error")]
     OnError,
@@ -73,60 +109,114 @@ impl Display for RestartMode {
     }
 }
 
-#[derive(Deserialize, Clone, Debug)]
+/// How many copies of a process run: a number, or "max" for one per CPU.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Instances {
+    Count(u32),
+    Keyword(String),
+}
+
+impl Instances {
+    pub fn count(&self) -> Result<u32, String> {
+        match self {
+            Self::Count(0) => Err("instances must be at least 1".to_string()),
+            Self::Count(count) => Ok(*count),
+            Self::Keyword(keyword) if keyword == "max" => Ok(std::thread::available_parallelism()
+                .map(|count| count.get() as u32)
+                .unwrap_or(1)),
+            Self::Keyword(keyword) => Err(format!(
+                "instances must be a number or \"max\", not \"{keyword}\""
+            )),
+        }
+    }
+}
+
+/// What kind of program a process runs.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ProcessKind {
+    /// Runs all the time and is restarted when it exits
+    #[default]
+    #[serde(rename = "service")]
+    Service,
+    /// Runs to completion on its `schedule`
+    #[serde(rename = "job")]
+    Job,
+    /// Runs to completion once, queued behind the other tasks. Created by `fpm run`
+    #[serde(rename = "task")]
+    Task,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
 pub struct Ecosystem {
     pub title: String,
-    pub start: String,
-    pub shell: String,
+    /// A command line for `shell`, or an argv to execute without one
+    pub start: StartCommand,
+    /// `/bin/sh` if not set, unused when `start` is an argv
+    pub shell: Option<String>,
     pub description: Option<String>,
+    /// Variables set for the child, on top of the daemon's environment
+    pub env: Option<BTreeMap<String, String>>,
+    /// Variables read from files only their owner can access, like `/run/secrets/db`. Only the
+    /// paths are kept, so the values never end up in backups or exports
+    pub env_secrets: Option<BTreeMap<String, String>>,
     pub restart: Option<RestartMode>,
+    /// Exit codes that are always restarted, whatever `restart` says
+    pub restart_on_exit_codes: Option<Vec<i32>>,
+    /// Exit codes that are never restarted, checked before `restart_on_exit_codes`
+    pub stop_on_exit_codes: Option<Vec<i32>>,
+    /// Whether a child killed by a signal is restarted. Follows `restart` when unset
+    pub restart_on_signal: Option<bool>,
     pub logs: Option<String>,
     pub logs_mode: Option<LogsMode>,
     pub watch: Option<Vec<String>>,
-}
-
-impl Display for Ecosystem {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        const REPLACE_FROM: &str = "\"";
-        const REPLACE_TO: &str = "\\\"";
-        let mut result = format!(
-            "ecosystem = {{ title=\"{}\", start=\"{}\", shell=\"{}\"",
-            self.title.replace(REPLACE_FROM, REPLACE_TO),
-            self.start.replace(REPLACE_FROM, REPLACE_TO),
-            self.shell.replace(REPLACE_FROM, REPLACE_TO)
-        );
-        if let Some(description) = self.description.clone() {
-            result = format!(
-                "{result}, description=\"{}\"",
-                description.replace(REPLACE_FROM, REPLACE_TO)
-            );
-        };
-        if let Some(restart) = self.restart {
-            result = format!("{result}, restart=\"{}\"", restart);
-        };
-        if let Some(logs) = self.logs.clone() {
-            result = format!(
-                "{result}, logs=\"{}\"",
-                logs.replace(REPLACE_FROM, REPLACE_TO)
-            );
-        };
-        if let Some(logs_mode) = self.logs_mode {
-            result = format!("{result}, logs_mode=\"{}\"", logs_mode);
-        };
-        if let Some(watch) = self.watch.clone() {
-            result = format!("{result}, watch=[",);
-            for (index, el) in watch.iter().enumerate() {
-                if index == 0 {
-                    result = format!("{result}\"{}\"", el.replace(REPLACE_FROM, REPLACE_TO));
-                } else {
-                    result = format!("{result}, \"{}\"", el.replace(REPLACE_FROM, REPLACE_TO));
-                }
-            }
-            result = format!("{result}]")
-        };
-        result = format!("{result} }}");
-        write!(f, "{result}")
-    }
+    /// Seconds to wait after SIGTERM before the process is killed
+    pub stop_timeout: Option<u64>,
+    pub instances: Option<Instances>,
+    /// Base port, instance N gets `PORT` set to this plus N
+    pub port: Option<u16>,
+    /// How `fpm reload` tells the replacement is up, also what dependents wait for
+    pub ready: Option<ReadyCheck>,
+    /// Titles of the processes that have to be ready before this one starts
+    pub depends_on: Option<Vec<String>>,
+    /// Restart along with any of `depends_on` restarting
+    pub restart_with_dependencies: Option<bool>,
+    #[serde(rename = "type")]
+    pub kind: Option<ProcessKind>,
+    /// Cron expression a job runs on
+    pub schedule: Option<String>,
+    /// Cron expression a service is restarted on
+    pub cron_restart: Option<String>,
+    /// Timezone of `schedule` and `cron_restart`, the local one by default
+    pub timezone: Option<String>,
+    /// Whether a job may start while its previous run is still going
+    pub allow_overlap: Option<bool>,
+    /// Seconds a run of a job or task may take before it is killed
+    pub timeout: Option<u64>,
+    /// How many times a failed run of a job or task is tried again
+    pub retries: Option<u32>,
+    /// Created from command line arguments instead of an ecosystem file, restarts and
+    /// reloads keep using it
+    pub adhoc: Option<bool>,
+    /// Name or uid the child runs as, the daemon has to run as root for this
+    pub user: Option<String>,
+    /// Name or gid, the primary group of `user` if not set
+    pub group: Option<String>,
+    /// Supplementary groups, the ones `user` is a member of if not set
+    pub groups: Option<Vec<String>>,
+    /// Limits set with setrlimit before exec
+    pub rlimit: Option<Rlimits>,
+    /// Limits of the cgroup the process runs in, needs a delegated cgroup v2 subtree
+    pub cgroup: Option<CgroupLimits>,
+    /// -20 (most favorable) to 19
+    pub nice: Option<i32>,
+    pub ionice: Option<IoNice>,
+    /// CPUs the process may run on, numbers or ranges like `"4-7"`
+    pub cpu_affinity: Option<Vec<Cpus>>,
+    /// -1000 to 1000, higher makes the OOM killer pick the process first
+    pub oom_score_adj: Option<i32>,
+    /// Hardening and namespaces, applied as far as the kernel and the daemon allow
+    pub sandbox: Option<Sandbox>,
 }
 
 impl Ecosystem {
@@ -142,180 +232,643 @@ impl Ecosystem {
             Err(err) => return Err(Box::new(err)),
         }
     }
-}
 
-#[derive(Deserialize, Clone, Debug)]
-pub enum ProcessStatus {
-    Initialized,
-    Started,
-    Starting,
-    Paused,
-    Pausing,
-    Error,
-    NotFound,
-}
+    /// The ecosystem `process` should run after a restart: a fresh read of its file, or the
+    /// one it already has if it wasn't started from a file.
+    pub async fn reread(process: &Process) -> Result<Self, Box<dyn Error>> {
+        if process.ecosystem.adhoc == Some(true) {
+            return Ok(process.ecosystem.clone());
+        }
+        Self::from_path(PathBuf::from(&process.path)).await
+    }
 
-impl Display for ProcessStatus {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ProcessStatus::Initialized => write!(f, "Initialized"),
-            ProcessStatus::Started => write!(f, "Started"),
-            ProcessStatus::Starting => write!(f, "Starting"),
-            ProcessStatus::Paused => write!(f, "Paused"),
-            ProcessStatus::Pausing => write!(f, "Pausing"),
-            ProcessStatus::Error => write!(f, "Error"),
-            ProcessStatus::NotFound => write!(f, "Not Found"),
+    /// Checks what can be checked before the process is created.
+    pub fn validate(&self, dir: &str) -> Result<(), String> {
+        self.check_schedule()?;
+        self.env_vars(dir)
+            .map_err(|err| format!("{}: {err}", self.title))?;
+        self.credentials()
+            .map_err(|err| format!("{}: {err}", self.title))?;
+        if let Some(rlimit) = &self.rlimit {
+            rlimit
+                .check()
+                .map_err(|err| format!("{}: {err}", self.title))?;
+        }
+        self.priority()
+            .map_err(|err| format!("{}: {err}", self.title))?;
+        if self.cgroup.is_some() && !crate::cgroups::available() {
+            return Err(format!(
+                "{}: cgroup limits need a cgroup v2 subtree delegated to fpmd",
+                self.title
+            ));
         }
+        Ok(())
     }
+
+    pub fn is_job(&self) -> bool {
+        self.kind == Some(ProcessKind::Job)
+    }
+
+    pub fn is_task(&self) -> bool {
+        self.kind == Some(ProcessKind::Task)
+    }
+
+    /// Jobs and tasks finish, they aren't restarted just because they exited.
+    pub fn runs_to_completion(&self) -> bool {
+        self.is_job() || self.is_task()
+    }
+
+    /// How many instances a freshly started process gets.
+    pub fn instance_count(&self) -> Result<u32, String> {
+        self.instances.as_ref().map_or(Ok(1), Instances::count)
+    }
+
+    /// Whether a child that went away this way gets another run.
+    pub fn should_restart(&self, exit: ExitReason) -> bool {
+        let mode = self.restart.unwrap_or(RestartMode::Always);
+        let listed = |codes: &Option<Vec<i32>>, code: i32| {
+            codes.as_ref().is_some_and(|codes| codes.contains(&code))
+        };
+        match exit {
+            ExitReason::Code(code) if listed(&self.stop_on_exit_codes, code) => false,
+            ExitReason::Code(code) if listed(&self.restart_on_exit_codes, code) => true,
+            ExitReason::Code(code) => match mode {
+                RestartMode::Always => true,
+                RestartMode::OnError => code != 0,
+                RestartMode::Never => false,
+            },
+            ExitReason::Signal(_) => self.restart_on_signal.unwrap_or(mode != RestartMode::Never),
+            ExitReason::Unknown => mode != RestartMode::Never,
+        }
+    }
+}
+
+/// Looks `program` up the same way a shell would: paths are checked as is,
+/// bare names are searched in `$PATH`.
+pub fn find_executable(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return if path.is_file() { Some(path) } else { None };
+    }
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
 }
 
 #[derive(Debug, Clone)]
 pub struct ProcessState {
-    pub status: ProcessStatus,
     pub ram_usage: u32,
     pub uid: Option<u32>,
-    pub tx: Sender<char>,
     pub should_stop: bool,
+    /// Everything the child has spawned, by pid with its start time. Kept up to date by the
+    /// reaper, orphans stay here after their parent exits.
+    pub descendants: HashMap<u32, u64>,
+    /// When the current (or last) child was started
+    pub started_at: Option<DateTime<Utc>>,
+    pub restarts: u32,
+    pub last_exit: Option<ExitReason>,
+    /// Why the last spawn failed
+    pub last_error: Option<String>,
+    /// The last `HISTORY_LIMIT` state changes, oldest first
+    pub history: VecDeque<(DateTime<Utc>, ProcessStatus)>,
+    /// The last `HISTORY_LIMIT` runs of a job or task, oldest first
+    pub runs: VecDeque<JobRun>,
+    /// Runs of a job or task since it was last started, retries included
+    pub attempts: u32,
+    /// What of the sandbox couldn't be applied at the last spawn
+    pub warnings: Vec<String>,
+    /// What the log file held each time a start truncated it, the bytes written add up
+    /// across runs with it
+    pub log_bytes_truncated: u64,
 }
 
 impl ProcessState {
-    pub fn new(
-        status: ProcessStatus,
-        ram_usage: u32,
-        uid: Option<u32>,
-        tx: Sender<char>,
-        should_stop: bool,
-    ) -> Self {
+    pub fn new(ram_usage: u32, uid: Option<u32>, should_stop: bool) -> Self {
         Self {
-            status,
             ram_usage,
             uid,
-            tx,
             should_stop,
+            descendants: HashMap::new(),
+            started_at: None,
+            restarts: 0,
+            last_exit: None,
+            last_error: None,
+            history: VecDeque::new(),
+            runs: VecDeque::new(),
+            attempts: 0,
+            warnings: vec![],
+            log_bytes_truncated: 0,
+        }
+    }
+
+    pub fn default() -> Self {
+        Self::new(0, None, false)
+    }
+
+    /// What a process rebuilt from a new ecosystem starts from. The counters and history go
+    /// on, nothing of the old child does. With `started_at` kept, the next spawn counts as a
+    /// restart.
+    pub fn carried_over(&self) -> Self {
+        Self {
+            started_at: self.started_at,
+            restarts: self.restarts,
+            last_exit: self.last_exit,
+            last_error: self.last_error.clone(),
+            history: self.history.clone(),
+            runs: self.runs.clone(),
+            log_bytes_truncated: self.log_bytes_truncated,
+            ..Self::default()
+        }
+    }
+
+    fn record(&mut self, status: ProcessStatus) {
+        if self.history.len() == HISTORY_LIMIT {
+            self.history.pop_front();
+        }
+        self.history.push_back((Utc::now(), status));
+    }
+
+    fn record_run(&mut self, run: JobRun) {
+        if self.runs.len() == HISTORY_LIMIT {
+            self.runs.pop_front();
         }
+        self.runs.push_back(run);
     }
 
-    pub fn default(tx: Sender<char>) -> Self {
-        Self::new(ProcessStatus::Initialized, 0, None, tx, false)
+    /// How long the current child has been running
+    pub fn uptime(&self, status: ProcessStatus) -> Option<chrono::Duration> {
+        match status {
+            ProcessStatus::Running | ProcessStatus::Stopping => Some(Utc::now() - self.started_at?),
+            _ => None,
+        }
     }
 }
 
-#[derive(Clone, Debug)]
+/// Resolves once the child is gone, with how it went.
+type ChildExit = Pin<Box<dyn Future<Output = ProcessEvent> + Send>>;
+
+fn wait_child(mut child: Child) -> ChildExit {
+    Box::pin(async move {
+        match child.wait().await {
+            Ok(status) => match status.signal() {
+                Some(signal) => ProcessEvent::Signaled(signal),
+                None => ProcessEvent::Exited(status.code()),
+            },
+            Err(_) => ProcessEvent::Exited(None),
+        }
+    })
+}
+
+fn wait_adopted(record: ChildRecord) -> ChildExit {
+    Box::pin(async move {
+        match children::wait_adopted(record).await {
+            Some(WaitStatus::Exited(_, code)) => ProcessEvent::Exited(Some(code)),
+            Some(WaitStatus::Signaled(_, signal, _)) => ProcessEvent::Signaled(signal as i32),
+            _ => ProcessEvent::Exited(None),
+        }
+    })
+}
+
+#[derive(Debug)]
 pub struct Process {
     pub id: u32,
+    /// Index of this copy among the processes sharing the title, starting at 0
+    pub instance: u32,
     pub ecosystem: Ecosystem,
     pub path: String,
     pub state: Arc<Mutex<ProcessState>>,
+    status: watch::Sender<ProcessStatus>,
+    commands: mpsc::UnboundedSender<ProcessCommand>,
 }
 
 impl Process {
-    pub fn new(id: u32, ecosystem: Ecosystem, path: String, process_state: ProcessState) -> Self {
-        Self {
+    /// Creates the process together with its supervisor task. Nothing is started until asked.
+    pub fn new(
+        id: u32,
+        instance: u32,
+        ecosystem: Ecosystem,
+        path: String,
+        process_state: ProcessState,
+    ) -> Arc<Self> {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let process = Arc::new(Self {
             id,
+            instance,
             ecosystem,
             path,
             state: Arc::new(Mutex::new(process_state)),
-        }
+            status: watch::Sender::new(ProcessStatus::Stopped),
+            commands,
+        });
+        tokio::spawn(process.clone().supervise(receiver));
+        process
     }
 
-    pub fn init(id: u32, ecosystem: Ecosystem, path: String) -> Self {
-        let (tx, _rx) = broadcast::channel::<char>(1);
-        Self::new(id, ecosystem, path, ProcessState::default(tx))
+    pub fn init(id: u32, instance: u32, ecosystem: Ecosystem, path: String) -> Arc<Self> {
+        Self::new(id, instance, ecosystem, path, ProcessState::default())
     }
 
-    pub fn from_backup(backup: BackupProcess) -> Self {
-        let (tx, _rx) = broadcast::channel::<char>(1);
+    pub fn from_backup(backup: BackupProcess) -> Arc<Self> {
         Self::new(
             backup.id,
+            backup.instance,
             backup.ecosystem,
             backup.path,
             ProcessState {
                 should_stop: backup.should_stop,
-                ..ProcessState::default(tx)
+                ..ProcessState::default()
             },
         )
     }
 
-    pub async fn spawn(self: Arc<Self>) -> Option<Child> {
-        let mut child = Command::new(&self.ecosystem.shell);
-        child
-            .current_dir(&self.path)
-            .arg("-c")
-            .arg(&self.ecosystem.start)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
-
-        match child.spawn() {
-            Ok(child) => Some(child),
-            Err(_err) => None,
+    /// The title, followed by the instance for clustered processes (`api:2`)
+    pub fn name(&self) -> String {
+        if self.ecosystem.instances.is_some() || self.instance > 0 {
+            format!("{}:{}", self.ecosystem.title, self.instance)
+        } else {
+            self.ecosystem.title.clone()
         }
     }
 
-    pub async fn run(self: Arc<Self>) {
-        let process = self;
-        if process.state.lock().await.should_stop || process.exists().await {
-            return;
-        }
-        process.state.lock().await.status = ProcessStatus::Starting;
-        'main: loop {
-            if process.state.lock().await.should_stop || process.exists().await {
-                return;
+    pub fn status(&self) -> ProcessStatus {
+        *self.status.borrow()
+    }
+
+    /// Moves to the state `event` leads to. Events that make no sense in the current state
+    /// are ignored and reported as `false`.
+    async fn transition(&self, event: ProcessEvent) -> bool {
+        let current = self.status();
+        match current.next(event) {
+            Some(next) => {
+                {
+                    let mut state = self.state.lock().await;
+                    if let Some(reason) = ExitReason::from_event(event) {
+                        state.last_exit = Some(reason);
+                    }
+                    state.record(next);
+                }
+                self.status.send_replace(next);
+                true
             }
-            println!("Starting...");
-            let child = process.clone().spawn().await;
-            let child = if let Some(child) = child {
-                child
-            } else {
-                break;
-            };
-            {
-                process.state.lock().await.uid = child.id();
+            None => {
+                eprintln!(
+                    "Process {}: ignored {:?} while {:?}",
+                    self.id, event, current
+                );
+                false
             }
-            process.state.lock().await.status = ProcessStatus::Started;
-            println!("Spawned child: {:?}", child.id());
+        }
+    }
 
-            // Handle logs
-            let child = process.logs(child).await;
+    fn send(&self, command: ProcessCommand) {
+        // The supervisor only goes away after a delete, there is nobody left to tell then
+        let _ = self.commands.send(command);
+    }
 
-            // Handle status
-            process.clone().watch_status(child).await;
+    pub async fn start(&self) {
+        {
+            let mut state = self.state.lock().await;
+            state.should_stop = false;
+            state.attempts = 0;
+        }
+        self.send(ProcessCommand::Start);
+    }
+
+    /// Stops the process and waits until it is stopped.
+    pub async fn stop(&self) {
+        self.state.lock().await.should_stop = true;
+        let mut status = self.status.subscribe();
+        self.send(ProcessCommand::Stop);
+        let _ = status
+            .wait_for(|status| *status == ProcessStatus::Stopped)
+            .await;
+    }
+
+    pub async fn restart(&self) {
+        self.state.lock().await.should_stop = false;
+        self.send(ProcessCommand::Restart);
+    }
+
+    pub fn adopt(&self, record: ChildRecord) {
+        self.send(ProcessCommand::Adopt(record));
+    }
+
+    /// Stops the process for good, the supervisor ends afterwards.
+    pub async fn delete(&self) {
+        self.state.lock().await.should_stop = true;
+        let mut status = self.status.subscribe();
+        self.send(ProcessCommand::Delete);
+        let _ = status
+            .wait_for(|status| *status == ProcessStatus::Stopped)
+            .await;
+    }
 
-            // Handle watch
-            process.clone().watch_files().await;
+    /// The only place a process changes state. Commands are handled one at a time, so a stop
+    /// can never race a restart.
+    async fn supervise(self: Arc<Self>, mut commands: mpsc::UnboundedReceiver<ProcessCommand>) {
+        let process = self;
+        let watcher = process.clone().watch_files();
+        let mut child: Option<ChildExit> = None;
+        let mut backoff: Option<Pin<Box<Sleep>>> = None;
+        let mut failures: u32 = 0;
+        let mut started_at = Instant::now();
+        let mut tick = process.ecosystem.next_tick();
+        let mut queued: Option<JoinHandle<()>> = None;
+        // The task queue slot of the current run, only held for as long as the child runs
+        let mut _slot: Option<OwnedSemaphorePermit> = None;
 
-            let mut rx = {
-                let state = process.state.lock().await;
-                state.tx.subscribe()
+        loop {
+            let command = tokio::select! {
+                command = commands.recv() => match command {
+                    Some(command) => command,
+                    None => break,
+                },
+                event = async { child.as_mut().unwrap().await }, if child.is_some() => {
+                    child = None;
+                    _slot = None;
+                    process.child_gone().await;
+                    process.finish_run(event).await;
+                    if !process.transition(event).await {
+                        continue;
+                    }
+                    if started_at.elapsed() >= BACKOFF_RESET {
+                        failures = 0;
+                    }
+                    if process.should_restart(event).await
+                        && process.transition(ProcessEvent::Backoff).await
+                    {
+                        let delay = BACKOFF_BASE
+                            .saturating_mul(2u32.saturating_pow(failures))
+                            .min(BACKOFF_MAX);
+                        failures += 1;
+                        backoff = Some(Box::pin(tokio::time::sleep(delay)));
+                    }
+                    continue;
+                },
+                _ = async { backoff.as_mut().unwrap().await }, if backoff.is_some() => {
+                    backoff = None;
+                    ProcessCommand::Start
+                },
+                _ = async { tick.as_mut().unwrap().await }, if tick.is_some() => {
+                    tick = process.ecosystem.next_tick();
+                    if process.state.lock().await.should_stop {
+                        continue;
+                    }
+                    if !process.ecosystem.is_job() {
+                        if process.status() != ProcessStatus::Running {
+                            continue;
+                        }
+                        println!("Scheduled restart of {}", process.name());
+                        ProcessCommand::Restart
+                    } else if child.is_none() {
+                        process.state.lock().await.attempts = 0;
+                        ProcessCommand::Start
+                    } else if process.ecosystem.allow_overlap == Some(true) {
+                        process.run_overlapping().await;
+                        continue;
+                    } else {
+                        println!("{} is still running, skipping this run", process.name());
+                        continue;
+                    }
+                },
             };
-            while let Ok(data) = rx.recv().await {
-                match data {
-                    'r' => {
-                        if process.exists().await {
-                            process.kill().await;
+
+            match command {
+                ProcessCommand::Start => {
+                    if !process.status().is_active() {
+                        backoff = None;
+                        child = process.begin(&mut queued).await;
+                        started_at = Instant::now();
+                    }
+                }
+                ProcessCommand::Run(permit) => {
+                    if process.status() == ProcessStatus::Queued {
+                        _slot = Some(permit);
+                        child = process.launch().await;
+                        if child.is_none() {
+                            _slot = None;
                         }
-                        continue 'main;
+                        started_at = Instant::now();
                     }
-                    's' => {
-                        if process.exists().await {
-                            process.kill().await;
+                }
+                ProcessCommand::Stop => {
+                    backoff = None;
+                    if let Some(queued) = queued.take() {
+                        queued.abort();
+                    }
+                    _slot = None;
+                    match child.take() {
+                        Some(running) => process.terminate(running).await,
+                        None if process.status() != ProcessStatus::Stopped => {
+                            process.transition(ProcessEvent::Stop).await;
                         }
-                        process.state.lock().await.uid = None;
-                        break 'main;
+                        None => {}
+                    }
+                }
+                ProcessCommand::Restart | ProcessCommand::FilesChanged => {
+                    if matches!(command, ProcessCommand::FilesChanged)
+                        && process.status() != ProcessStatus::Running
+                    {
+                        continue;
+                    }
+                    backoff = None;
+                    failures = 0;
+                    if let Some(running) = child.take() {
+                        process.terminate(running).await;
+                    }
+                    _slot = None;
+                    if process.status() == ProcessStatus::Queued {
+                        continue;
+                    }
+                    child = process.begin(&mut queued).await;
+                    started_at = Instant::now();
+                }
+                ProcessCommand::Adopt(record) => {
+                    if process.status().is_active() {
+                        continue;
+                    }
+                    backoff = None;
+                    {
+                        let mut state = process.state.lock().await;
+                        state.uid = Some(record.pid);
+                        state.should_stop = false;
+                        state.started_at = children::started_at(record.pid);
+                    }
+                    children::track(process.id, record.pid);
+                    process.transition(ProcessEvent::Start).await;
+                    process.transition(ProcessEvent::Spawned).await;
+                    println!("Adopted child: {}", record.pid);
+                    child = Some(wait_adopted(record));
+                    started_at = Instant::now();
+                }
+                ProcessCommand::Delete => {
+                    if let Some(queued) = queued.take() {
+                        queued.abort();
                     }
-                    _ => {}
+                    if let Some(running) = child.take() {
+                        process.terminate(running).await;
+                    } else if process.status() != ProcessStatus::Stopped {
+                        process.transition(ProcessEvent::Stop).await;
+                    }
+                    break;
                 }
             }
         }
+        if let Some(watcher) = watcher {
+            watcher.abort();
+        }
+        if let Some(queued) = queued {
+            queued.abort();
+        }
+        if let Some(cgroup) = Cgroup::of(process.id) {
+            cgroup.remove();
+        }
     }
 
-    pub async fn watch_files(self: Arc<Self>) {
-        let process_tx = self.state.lock().await.tx.clone();
-        tokio::spawn(async move {
-            let watch = if let Some(watch) = self.ecosystem.watch.clone() {
-                watch
-            } else {
-                return;
-            };
+    /// Spawns a new child, returning how to wait for it if that worked.
+    async fn launch(&self) -> Option<ChildExit> {
+        if !self.transition(ProcessEvent::Start).await {
+            return None;
+        }
+        println!("Starting...");
+        match self.spawn().await {
+            Ok(child) => {
+                let pid = child.id();
+                let restarted = {
+                    let mut state = self.state.lock().await;
+                    // Every run of a job is a fresh start, not a restart
+                    let restarted =
+                        state.started_at.is_some() && !self.ecosystem.runs_to_completion();
+                    if restarted {
+                        state.restarts += 1;
+                    }
+                    state.attempts += 1;
+                    state.uid = pid;
+                    state.started_at = Some(Utc::now());
+                    state.last_error = None;
+                    restarted
+                };
+                if restarted {
+                    dependencies::restarted(self.id);
+                }
+                if let Some(pid) = pid {
+                    children::remember(self.id, pid).await;
+                }
+                self.transition(ProcessEvent::Spawned).await;
+                println!("Spawned child: {:?}", pid);
+                match self.ecosystem.timeout {
+                    Some(timeout) if self.ecosystem.runs_to_completion() => {
+                        Some(self.with_timeout(wait_child(child), timeout))
+                    }
+                    _ => Some(wait_child(child)),
+                }
+            }
+            Err(err) => {
+                eprintln!("Can't spawn process {}: {}", self.id, err);
+                self.state.lock().await.last_error = Some(err.to_string());
+                self.transition(ProcessEvent::SpawnFailed).await;
+                None
+            }
+        }
+    }
+
+    async fn child_gone(&self) {
+        let pid = self.state.lock().await.uid.take();
+        match pid {
+            Some(pid) => children::release(self.id, pid).await,
+            None => children::forget(self.id).await,
+        }
+    }
+
+    async fn should_restart(&self, event: ProcessEvent) -> bool {
+        let state = self.state.lock().await;
+        if state.should_stop {
+            return false;
+        }
+        // Jobs and tasks are only tried again after a failed run, as many times as `retries`
+        // allows. A job waits for its next scheduled run after that.
+        if self.ecosystem.runs_to_completion() {
+            return event != ProcessEvent::Exited(Some(0))
+                && state.attempts <= self.ecosystem.retries.unwrap_or(0);
+        }
+        ExitReason::from_event(event).is_some_and(|exit| self.ecosystem.should_restart(exit))
+    }
+
+    pub async fn spawn(&self) -> std::io::Result<Child> {
+        let (stdout, stderr) = match self.log_stdio().await {
+            Ok(stdio) => stdio,
+            Err(err) => {
+                eprintln!("Can't open logs of process {}: {}", self.id, err);
+                (Stdio::null(), Stdio::null())
+            }
+        };
+        let mut vars = vec![
+            (ID_ENV, self.id.to_string()),
+            (INSTANCE_ENV, self.instance.to_string()),
+        ];
+        if let Some(port) = self.ecosystem.port {
+            match u16::try_from(self.instance)
+                .ok()
+                .and_then(|instance| port.checked_add(instance))
+            {
+                Some(port) => vars.push((PORT_ENV, port.to_string())),
+                None => eprintln!("Port of process {} is out of range", self.id),
+            }
+        }
+        let credentials = self.ecosystem.credentials()?;
+        if let Some(credentials) = &credentials {
+            vars.extend(credentials.vars());
+        }
+        vars.extend(self.ecosystem.env_vars(&self.path)?);
+        let mut child = self.ecosystem.command(&vars)?;
+        if let Some(rlimit) = &self.ecosystem.rlimit {
+            rlimit.apply(&mut child)?;
+        }
+        if let Some(cgroup) = Cgroup::of(self.id) {
+            cgroup.create(&self.ecosystem.cgroup.clone().unwrap_or_default())?;
+            cgroup.apply(&mut child)?;
+        } else if self.ecosystem.cgroup.is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "cgroup limits need a cgroup v2 subtree delegated to fpmd",
+            ));
+        }
+        self.ecosystem
+            .priority()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?
+            .apply(&mut child);
+        let (sandbox, warnings) = self.ecosystem.sandbox_plan(&self.path);
+        for warning in &warnings {
+            eprintln!("Sandbox of process {}: {}", self.id, warning);
+        }
+        self.state.lock().await.warnings = warnings;
+        sandbox.apply(&mut child);
+        if let Some(credentials) = &credentials {
+            credentials.apply(&mut child);
+        }
+        child
+            .current_dir(&self.path)
+            .envs(vars)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr);
+
+        match child.spawn() {
+            Ok(child) => {
+                // Registered right away so the reaper never races us for its exit status
+                if let Some(pid) = child.id() {
+                    children::track(self.id, pid);
+                }
+                Ok(child)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn watch_files(self: Arc<Self>) -> Option<JoinHandle<()>> {
+        let watch = self.ecosystem.watch.clone()?;
+        Some(tokio::spawn(async move {
             let (tx, mut rx) = mpsc::channel(100);
 
             let mut watcher = notify::recommended_watcher(move |res: Result<Event, _>| {
@@ -333,180 +886,300 @@ impl Process {
             }
 
             while let Some(Ok(event)) = rx.recv().await {
-                for path in &event.paths {
-                    if let Some(logs) = self.ecosystem.logs.clone() {
-                        println!("{:#?}", path);
-                        if path.ends_with(logs) {
-                            continue;
-                        }
-                    }
-                    process_tx.send('r');
+                let logs = self.ecosystem.logs.clone();
+                let changed = event.paths.iter().any(|path| match &logs {
+                    Some(logs) => !path.ends_with(logs),
+                    None => true,
+                });
+                if changed {
+                    self.send(ProcessCommand::FilesChanged);
                 }
             }
-        });
+        }))
     }
 
-    pub async fn logs(&self, mut child: Child) -> Child {
-        let process = self;
-        let logs = if let Some(logs) = process.ecosystem.logs.clone() {
-            logs
-        } else {
-            return child;
+    /// Where the output of the child goes, `None` when it is thrown away.
+    pub fn log_path(&self) -> Option<PathBuf> {
+        let logs = self.ecosystem.logs.as_ref()?;
+        Some(PathBuf::from(&self.path).join(logs))
+    }
+
+    /// Children write straight into the log file rather than through a pipe to the daemon,
+    /// so they survive the daemon going away and can be adopted by the next one.
+    async fn log_stdio(&self) -> std::io::Result<(Stdio, Stdio)> {
+        let Some(log_path) = self.log_path() else {
+            return Ok((Stdio::null(), Stdio::null()));
         };
-        let logs = Arc::new(format!("{}/{}", &process.path, logs));
-        let stdout = child.stdout.take().expect("Error: Can't handle to stdout");
-        let stderr = child.stderr.take().expect("Error: Can't handle to stderr");
-        let mut stdout_reader = BufReader::new(stdout).lines();
-        let mut stderr_reader = BufReader::new(stderr).lines();
-
-        let log_path = logs.clone();
-        match process.ecosystem.logs_mode {
+        let mut options = fs::OpenOptions::new();
+        options.create(true);
+        let file = match self.ecosystem.logs_mode {
             Some(LogsMode::Append) => {
-                let file_content = fs::read_to_string(&*log_path)
-                    .await
-                    .unwrap_or("".to_string());
+                let mut file = options.append(true).open(&log_path).await?;
                 let now: DateTime<Utc> = Utc::now();
                 let time_string: String = now.format("%H:%M:%S").to_string();
                 let title = format!("=====STARTED ON {}=====", time_string);
-                let _ = fs::write(&*log_path, format!("{}\n\n{}\n\n", file_content, title)).await;
+                file.write_all(format!("\n\n{}\n\n", title).as_bytes())
+                    .await?;
+                file
             }
             _ => {
-                let _ = fs::write(&*log_path, "").await;
+                if let Ok(metadata) = fs::metadata(&log_path).await {
+                    self.state.lock().await.log_bytes_truncated += metadata.len();
+                }
+                options.write(true).truncate(true).open(&log_path).await?
             }
-        }
+        };
+        let stdout = file.into_std().await;
+        let stderr = stdout.try_clone()?;
+        Ok((Stdio::from(stdout), Stdio::from(stderr)))
+    }
 
-        let log_path = logs.clone();
-        let _stdout_task = tokio::spawn(async move {
-            while let Ok(Some(line)) = stdout_reader.next_line().await {
-                println!("{}", line);
-                let file_content = fs::read_to_string(&*log_path)
-                    .await
-                    .unwrap_or("".to_string());
-                let _ = fs::write(&*log_path, format!("{}\n{}", file_content, line)).await;
-            }
-        });
-        let log_path = logs.clone();
-        let _stderr_task = tokio::spawn(async move {
-            while let Ok(Some(line)) = stderr_reader.next_line().await {
-                let file_content = fs::read_to_string(&*log_path)
-                    .await
-                    .unwrap_or("".to_string());
-                let _ = fs::write(&*log_path, format!("{}\n{}", file_content, line)).await;
+    /// Sends SIGTERM to the child and everything it spawned, then SIGKILL to whatever is
+    /// still around after `stop_timeout`.
+    async fn terminate(&self, mut child: ChildExit) {
+        self.transition(ProcessEvent::Stop).await;
+        let (uid, descendants) = {
+            let state = self.state.lock().await;
+            let descendants: Vec<Pid> = state
+                .descendants
+                .keys()
+                .map(|pid| Pid::from_raw(*pid as i32))
+                .collect();
+            (state.uid.map(|pid| Pid::from_raw(pid as i32)), descendants)
+        };
+        for pid in uid.iter().chain(&descendants) {
+            if let Err(err) = kill(*pid, Signal::SIGTERM) {
+                eprintln!("Failed to send SIGTERM to {}: {}", pid, err);
             }
-        });
-
-        child
-    }
+        }
 
-    pub async fn watch_status(self: Arc<Self>, mut child: Child) {
-        let process = self;
-        let restart_mode = process.ecosystem.restart.unwrap_or(RestartMode::Always);
-        let status = child
-            .wait()
-            .await
-            .expect("child process encountered an error");
-        let send_code = async |ch: char| {
-            process.state.lock().await.tx.send(ch);
+        let stop_timeout = self.ecosystem.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT);
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(stop_timeout);
+        let event = match tokio::time::timeout_at(deadline, &mut child).await {
+            Ok(event) => event,
+            Err(_) => {
+                println!("Process didn't stop, killing...");
+                if let Some(pid) = uid {
+                    let _ = kill(pid, Signal::SIGKILL);
+                }
+                if let Some(cgroup) = Cgroup::of(self.id) {
+                    cgroup.kill();
+                }
+                child.await
+            }
         };
-        match restart_mode {
-            RestartMode::Always => {
-                send_code('r').await;
+
+        let mut descendants = descendants;
+        loop {
+            descendants.retain(|pid| kill(*pid, None).is_ok());
+            if descendants.is_empty() {
+                break;
             }
-            RestartMode::OnError => {
-                if !status.success() {
-                    send_code('r').await;
+            if tokio::time::Instant::now() >= deadline {
+                for pid in &descendants {
+                    let _ = kill(*pid, Signal::SIGKILL);
                 }
+                break;
             }
-            _ => send_code('s').await,
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        // Whatever got away from the descendant tracking is still in the cgroup
+        if let Some(cgroup) = Cgroup::of(self.id).filter(|cgroup| !cgroup.is_empty()) {
+            cgroup.kill();
         }
-        println!("child status was: {}", status);
+        self.state.lock().await.descendants.clear();
+        self.child_gone().await;
+        self.finish_run(event).await;
+        self.transition(event).await;
     }
 
-    pub async fn kill(&self) {
+    /// Records how the current run of a job went.
+    async fn finish_run(&self, event: ProcessEvent) {
+        if !self.ecosystem.runs_to_completion() {
+            return;
+        }
         let mut state = self.state.lock().await;
+        let started_at = state.started_at.unwrap_or_else(Utc::now);
+        state.record_run(JobRun {
+            started_at,
+            duration: Utc::now() - started_at,
+            exit: ExitReason::from_event(event),
+        });
+    }
 
-        if let Some(pid) = state.uid {
-            let pid = Pid::from_raw(pid as i32);
-
-            if let Err(err) = kill(pid, Signal::SIGTERM) {
-                eprintln!("Failed to send SIGTERM: {}", err);
+    /// Starts a run of a job next to the one still going. It is tracked as a descendant, so
+    /// stopping the job stops it too.
+    async fn run_overlapping(self: &Arc<Self>) {
+        let child = match self.spawn().await {
+            Ok(child) => child,
+            Err(err) => {
+                eprintln!("Can't spawn process {}: {}", self.id, err);
                 return;
-            } else {
-                state.status = ProcessStatus::Paused;
             }
+        };
+        let Some(pid) = child.id() else {
+            return;
+        };
+        println!("Spawned overlapping run: {}", pid);
+        let started_at = Utc::now();
+        if let Some(start_time) = children::start_time(pid) {
+            self.state.lock().await.descendants.insert(pid, start_time);
+        }
+        let process = self.clone();
+        tokio::spawn(async move {
+            let event = wait_child(child).await;
+            children::untrack(pid);
+            process.state.lock().await.record_run(JobRun {
+                started_at,
+                duration: Utc::now() - started_at,
+                exit: ExitReason::from_event(event),
+            });
+        });
+    }
+
+    pub fn exists(&self) -> bool {
+        self.status().is_active()
+    }
+}
+
+/// Brings back the processes of a backup. Children still running are adopted, the rest is
+/// started in dependency order in the background.
+pub async fn start_processes(app_state: Arc<Mutex<crate::AppState>>, backup: Vec<BackupProcess>) {
+    let mut known = HashSet::new();
+    let mut to_start = vec![];
+    for backup_process in backup {
+        // Ids are kept as they were: children left behind by the previous daemon are
+        // matched back to their process by id
+        let id = backup_process.id;
+        {
+            let mut app_state_guard = app_state.lock().await;
+            app_state_guard.id = app_state_guard.id.max(id + 1);
+        }
 
-            for _ in 0..30 {
-                if kill(pid, None).is_err() {
-                    println!("Process gracefully exited");
-                    state.status = ProcessStatus::Paused;
-                    return;
+        // The backup carries the ecosystem inline, so only the working directory has to exist
+        if !PathBuf::from(&backup_process.path).exists() {
+            eprintln!("Invalid path process {id}");
+            continue;
+        };
+        let process = Process::from_backup(backup_process);
+
+        app_state.lock().await.processes.insert(id, process.clone());
+        known.insert(id);
+
+        match children::recall(id).await {
+            Some(record) => process.adopt(record),
+            // Jobs only run on their schedule, tasks only when asked to
+            None => {
+                if !process.state.lock().await.should_stop
+                    && !process.ecosystem.runs_to_completion()
+                {
+                    to_start.push(process);
                 }
-                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
             }
-
-            println!("Process didn't stop, killing...");
-            let _ = kill(pid, Signal::SIGKILL);
-            state.status = ProcessStatus::Paused;
-        } else {
-            state.status = ProcessStatus::Paused;
         }
     }
+    children::forget_unknown(&known).await;
 
-    pub async fn stop(&self) {
-        {
-            let mut state = self.state.lock().await;
-            state.status = ProcessStatus::Pausing;
-            state.should_stop = true;
-            state.tx.send('s');
+    tokio::spawn(async move {
+        if let Err(err) = dependencies::start_in_order(app_state, to_start.clone(), false).await {
+            eprintln!("Can't order processes, starting them all at once: {err}");
+            for process in to_start {
+                process.start().await;
+            }
         }
-        self.kill().await;
-    }
+    });
+}
 
-    pub async fn restart(&self) {
-        self.kill().await;
-        {
-            let mut state = self.state.lock().await;
-            state.status = ProcessStatus::Pausing;
-            state.should_stop = false;
-            state.tx.send('r');
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ecosystem(restart: Option<RestartMode>) -> Ecosystem {
+        Ecosystem {
+            restart,
+            ..Default::default()
         }
     }
 
-    pub async fn exists(&self) -> bool {
-        if let Some(uid) = self.state.lock().await.uid {
-            Path::new(&format!("/proc/{}", uid)).exists()
-        } else {
-            false
+    #[test]
+    fn restarts_by_mode() {
+        use ExitReason::{Code, Signal, Unknown};
+        let cases = [
+            (None, Code(0), true),
+            (None, Code(1), true),
+            (None, Signal(9), true),
+            (None, Unknown, true),
+            (Some(RestartMode::OnError), Code(0), false),
+            (Some(RestartMode::OnError), Code(2), true),
+            (Some(RestartMode::OnError), Signal(15), true),
+            (Some(RestartMode::OnError), Unknown, true),
+            (Some(RestartMode::Never), Code(1), false),
+            (Some(RestartMode::Never), Signal(9), false),
+            (Some(RestartMode::Never), Unknown, false),
+        ];
+        for (mode, exit, expected) in cases {
+            assert_eq!(
+                ecosystem(mode).should_restart(exit),
+                expected,
+                "{mode:?} {exit:?}"
+            );
         }
     }
-}
 
-pub async fn start_processes(app_state: Arc<Mutex<crate::AppState>>) {
-    let backup = backup::load_backup().await;
-    for process in backup {
-        let process = Process::from_backup(process);
-        let path = PathBuf::from(&process.path);
-        let ecosystem_path = path.join(ECOSYSTEM_NAME);
-        if ecosystem_path.exists() {
-            let id = app_state.lock().await.id;
-            app_state.lock().await.id += 1;
-
-            if !path.exists() {
-                eprintln!("Invalid path process {id}");
-                {
-                    let mut process_guard = process.state.lock().await;
-                    process_guard.should_stop = true;
-                    process_guard.status = ProcessStatus::NotFound;
-                }
-                continue;
-            };
-            let process = Arc::new(process);
+    #[test]
+    fn exit_code_lists_override_the_mode() {
+        let mut never = ecosystem(Some(RestartMode::Never));
+        never.restart_on_exit_codes = Some(vec![75]);
+        assert!(never.should_restart(ExitReason::Code(75)));
+        assert!(!never.should_restart(ExitReason::Code(1)));
 
-            app_state.lock().await.processes.insert(id, process.clone());
+        let mut always = ecosystem(Some(RestartMode::Always));
+        always.stop_on_exit_codes = Some(vec![0, 78]);
+        assert!(!always.should_restart(ExitReason::Code(78)));
+        assert!(!always.should_restart(ExitReason::Code(0)));
+        assert!(always.should_restart(ExitReason::Code(1)));
+        // Codes only match exits, not signals with the same number
+        assert!(always.should_restart(ExitReason::Signal(78)));
 
-            tokio::spawn(async move {
-                process.run().await;
-            });
-        }
+        // A code in both lists stops
+        always.restart_on_exit_codes = Some(vec![78]);
+        assert!(!always.should_restart(ExitReason::Code(78)));
+    }
+
+    #[test]
+    fn restart_on_signal_overrides_the_mode() {
+        let mut never = ecosystem(Some(RestartMode::Never));
+        never.restart_on_signal = Some(true);
+        assert!(never.should_restart(ExitReason::Signal(9)));
+        assert!(!never.should_restart(ExitReason::Code(1)));
+
+        let mut always = ecosystem(None);
+        always.restart_on_signal = Some(false);
+        assert!(!always.should_restart(ExitReason::Signal(9)));
+        assert!(always.should_restart(ExitReason::Code(1)));
+        // An exit nobody could collect isn't a signal
+        assert!(always.should_restart(ExitReason::Unknown));
+    }
+
+    #[test]
+    fn restarts_survive_a_restart() {
+        let mut state = ProcessState::new(0, Some(4242), false);
+        state.restarts = 3;
+        state.started_at = Some(Utc::now());
+        state.last_exit = Some(ExitReason::Code(1));
+        state.record(ProcessStatus::Running);
+        state.descendants.insert(4243, 1);
+        state.attempts = 2;
+
+        let replaced = state.carried_over();
+        assert_eq!(replaced.restarts, 3);
+        assert_eq!(replaced.last_exit, Some(ExitReason::Code(1)));
+        assert_eq!(replaced.history.len(), 1);
+        // Kept so the spawn of the replacement counts as the fourth restart
+        assert_eq!(replaced.started_at, state.started_at);
+        assert_eq!(replaced.uid, None);
+        assert!(replaced.descendants.is_empty());
+        assert_eq!(replaced.attempts, 0);
     }
 }
@@ -0,0 +1,140 @@
+use std::{env, fmt::Display, io};
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use super::Ecosystem;
+
+const DEFAULT_SHELL: &str = "/bin/sh";
+
+/// How a process is started: a command line run by `shell`, or an argv executed directly.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum StartCommand {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+impl Default for StartCommand {
+    fn default() -> Self {
+        Self::Shell(String::new())
+    }
+}
+
+impl Display for StartCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Shell(line) => write!(f, "{line}"),
+            Self::Argv(argv) => write!(f, "{argv:?}"),
+        }
+    }
+}
+
+impl Ecosystem {
+    pub fn shell(&self) -> &str {
+        self.shell.as_deref().unwrap_or(DEFAULT_SHELL)
+    }
+
+    /// The executable that gets started, the shell unless `start` is an argv.
+    pub fn program(&self) -> Option<&str> {
+        match &self.start {
+            StartCommand::Shell(_) => Some(self.shell()),
+            StartCommand::Argv(argv) => argv.first().map(String::as_str),
+        }
+    }
+
+    /// The command that starts the process. `${VAR}` in an argv is replaced with `vars`, where
+    /// the last of a name wins as it does in the environment, or the daemon's own environment,
+    /// so the child sees what a shell would have given it.
+    pub fn command(&self, vars: &[(&str, String)]) -> io::Result<Command> {
+        match &self.start {
+            StartCommand::Shell(line) => {
+                let mut command = Command::new(self.shell());
+                command.arg("-c").arg(line);
+                Ok(command)
+            }
+            StartCommand::Argv(argv) => {
+                let lookup = |name: &str| {
+                    vars.iter()
+                        .rfind(|(var, _)| *var == name)
+                        .map(|(_, value)| value.clone())
+                        .or_else(|| env::var(name).ok())
+                };
+                let argv = argv
+                    .iter()
+                    .map(|arg| expand(arg, lookup))
+                    .collect::<Result<Vec<String>, String>>()
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+                let Some((program, args)) = argv.split_first() else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "start is an empty list",
+                    ));
+                };
+                let mut command = Command::new(program);
+                command.args(args);
+                Ok(command)
+            }
+        }
+    }
+}
+
+/// Replaces every `${VAR}` in `arg`, `$$` stands for a plain `$`. A variable that isn't set
+/// is an error rather than an empty string, the process would most likely misbehave.
+fn expand(arg: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut expanded = String::with_capacity(arg.len());
+    let mut rest = arg;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("unclosed ${{ in \"{arg}\""))?;
+            let name = &after[..end];
+            let value = lookup(name).ok_or_else(|| format!("${{{name}}} is not set"))?;
+            expanded.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            expanded.push('$');
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "PORT" => Some("8080".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expands_variables() {
+        assert_eq!(expand("--port=${PORT}", lookup).unwrap(), "--port=8080");
+        assert_eq!(expand("${PORT}${PORT}", lookup).unwrap(), "80808080");
+        assert_eq!(expand("a${EMPTY}b", lookup).unwrap(), "ab");
+    }
+
+    #[test]
+    fn leaves_other_dollars_alone() {
+        assert_eq!(expand("$PORT", lookup).unwrap(), "$PORT");
+        assert_eq!(expand("cost: 5$", lookup).unwrap(), "cost: 5$");
+        assert_eq!(expand("$${PORT}", lookup).unwrap(), "${PORT}");
+    }
+
+    #[test]
+    fn rejects_unset_and_unclosed() {
+        assert!(expand("${MISSING}", lookup).is_err());
+        assert!(expand("${PORT", lookup).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use std::{ffi::CString, io};
+
+use nix::unistd::{Gid, Group, Uid, User, geteuid, getgrouplist, setgid, setgroups, setuid};
+use tokio::process::Command;
+
+use super::Ecosystem;
+
+/// Who a child runs as, resolved from `user`, `group` and `groups` right before it starts.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub user: User,
+    pub gid: Gid,
+    pub groups: Vec<Gid>,
+}
+
+fn error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, message)
+}
+
+fn user(name: &str) -> io::Result<User> {
+    let user = match name.parse::<u32>() {
+        Ok(uid) => User::from_uid(Uid::from_raw(uid)),
+        Err(_) => User::from_name(name),
+    };
+    user.map_err(io::Error::from)?
+        .ok_or_else(|| error(format!("unknown user \"{name}\"")))
+}
+
+fn group(name: &str) -> io::Result<Gid> {
+    let group = match name.parse::<u32>() {
+        Ok(gid) => Group::from_gid(Gid::from_raw(gid)),
+        Err(_) => Group::from_name(name),
+    };
+    group
+        .map_err(io::Error::from)?
+        .map(|group| group.gid)
+        .ok_or_else(|| error(format!("unknown group \"{name}\"")))
+}
+
+impl Ecosystem {
+    /// The user and groups to switch to, `None` when the child runs as the daemon does. Only
+    /// root can switch, anyone else gets an error saying so instead of a half-applied change.
+    pub fn credentials(&self) -> io::Result<Option<Credentials>> {
+        let Some(name) = &self.user else {
+            if self.group.is_some() || self.groups.is_some() {
+                return Err(error("group and groups need a user to be set".to_string()));
+            }
+            return Ok(None);
+        };
+        let user = user(name)?;
+        let gid = match &self.group {
+            Some(name) => group(name)?,
+            None => user.gid,
+        };
+        let groups = match &self.groups {
+            Some(names) => names
+                .iter()
+                .map(|name| group(name))
+                .collect::<io::Result<Vec<Gid>>>()?,
+            None => {
+                let name = CString::new(user.name.as_str()).map_err(io::Error::other)?;
+                getgrouplist(&name, gid).map_err(io::Error::from)?
+            }
+        };
+        if !geteuid().is_root() && user.uid != geteuid() {
+            return Err(error(format!(
+                "fpmd runs as uid {} and can't switch to user {}, only root can",
+                geteuid(),
+                user.name
+            )));
+        }
+        Ok(Some(Credentials { user, gid, groups }))
+    }
+}
+
+impl Credentials {
+    /// What the child finds in its environment, as if it had logged in.
+    pub fn vars(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("HOME", self.user.dir.to_string_lossy().to_string()),
+            ("USER", self.user.name.clone()),
+            ("LOGNAME", self.user.name.clone()),
+        ]
+    }
+
+    /// Switches the child to the user right before it executes. Groups go first, they can't
+    /// be changed any more once the user is.
+    pub fn apply(&self, command: &mut Command) {
+        let Credentials { user, gid, groups } = self.clone();
+        let uid = user.uid;
+        let switch = geteuid().is_root();
+        // Runs between fork and exec, where nothing may allocate
+        unsafe {
+            command.pre_exec(move || {
+                if switch {
+                    setgroups(&groups)?;
+                    setgid(gid)?;
+                    setuid(uid)?;
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ecosystem(user: Option<&str>, group: Option<&str>, groups: Option<&[&str]>) -> Ecosystem {
+        Ecosystem {
+            user: user.map(str::to_string),
+            group: group.map(str::to_string),
+            groups: groups.map(|groups| groups.iter().map(|group| group.to_string()).collect()),
+            ..Default::default()
+        }
+    }
+
+    fn myself() -> User {
+        User::from_uid(geteuid()).unwrap().unwrap()
+    }
+
+    #[test]
+    fn runs_as_the_daemon_without_a_user() {
+        assert!(ecosystem(None, None, None).credentials().unwrap().is_none());
+        let err = ecosystem(None, Some("0"), None).credentials().unwrap_err();
+        assert_eq!(err.to_string(), "group and groups need a user to be set");
+        assert!(ecosystem(None, None, Some(&["0"])).credentials().is_err());
+    }
+
+    #[test]
+    fn resolves_the_own_user() {
+        let me = myself();
+        let uid = me.uid.to_string();
+        for name in [me.name.as_str(), uid.as_str()] {
+            let credentials = ecosystem(Some(name), None, None)
+                .credentials()
+                .unwrap()
+                .unwrap();
+            assert_eq!(credentials.user.uid, me.uid);
+            assert_eq!(credentials.gid, me.gid);
+            assert!(credentials.groups.contains(&me.gid));
+        }
+
+        let gid = me.gid.to_string();
+        let credentials = ecosystem(Some(&me.name), Some(&gid), Some(&[&gid]))
+            .credentials()
+            .unwrap()
+            .unwrap();
+        assert_eq!(credentials.groups, [me.gid]);
+        assert_eq!(credentials.vars()[1], ("USER", me.name.clone()));
+    }
+
+    #[test]
+    fn refuses_unknown_users_and_groups() {
+        let err = ecosystem(Some("fpm-no-such-user"), None, None)
+            .credentials()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unknown user \"fpm-no-such-user\"");
+
+        let me = myself();
+        let err = ecosystem(Some(&me.name), Some("fpm-no-such-group"), None)
+            .credentials()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unknown group \"fpm-no-such-group\"");
+        let err = ecosystem(Some(&me.name), None, Some(&["fpm-no-such-group"]))
+            .credentials()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unknown group \"fpm-no-such-group\"");
+    }
+
+    #[test]
+    fn only_root_switches_users() {
+        // Whoever the tests run as, someone else is root or nobody
+        let other = if geteuid().is_root() {
+            "nobody"
+        } else {
+            "root"
+        };
+        let Ok(Some(_)) = User::from_name(other) else {
+            return;
+        };
+        let credentials = ecosystem(Some(other), None, None).credentials();
+        if geteuid().is_root() {
+            assert_eq!(credentials.unwrap().unwrap().user.name, other);
+        } else {
+            let err = credentials.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+            assert!(
+                err.to_string()
+                    .ends_with("can't switch to user root, only root can")
+            );
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::{fmt::Display, io};
+
+use nix::sys::resource::{RLIM_INFINITY, Resource, setrlimit};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+/// A number, a size like `"512M"`, or `"unlimited"`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Limit {
+    Number(u64),
+    Text(String),
+}
+
+impl Limit {
+    /// The limit in plain units, `None` for no limit at all.
+    pub fn value(&self) -> Result<Option<u64>, String> {
+        let text = match self {
+            Self::Number(number) => return Ok(Some(*number)),
+            Self::Text(text) => text.trim(),
+        };
+        if ["unlimited", "infinity", "max"].contains(&text) {
+            return Ok(None);
+        }
+        let (number, multiplier) = match text.char_indices().last() {
+            Some((index, 'K' | 'k')) => (&text[..index], 1 << 10),
+            Some((index, 'M' | 'm')) => (&text[..index], 1 << 20),
+            Some((index, 'G' | 'g')) => (&text[..index], 1 << 30),
+            Some((index, 'T' | 't')) => (&text[..index], 1 << 40),
+            _ => (text, 1),
+        };
+        number
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .and_then(|number| number.checked_mul(multiplier))
+            .map(Some)
+            .ok_or_else(|| format!("invalid limit \"{text}\""))
+    }
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(number) => write!(f, "{number}"),
+            Self::Text(text) => write!(f, "{text}"),
+        }
+    }
+}
+
+/// Resource limits set on the child right before it executes, soft and hard alike.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct Rlimits {
+    /// Open files
+    pub nofile: Option<Limit>,
+    /// Processes of the user
+    pub nproc: Option<Limit>,
+    /// Size of core dumps, 0 turns them off
+    pub core: Option<Limit>,
+    /// Address space
+    #[serde(rename = "as")]
+    pub address_space: Option<Limit>,
+}
+
+/// What goes into the cgroup of the process, in the kernel's own format.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct CgroupLimits {
+    /// `memory.max`, bytes or a size like `"512M"`
+    pub memory_max: Option<Limit>,
+    /// `cpu.max`, `"<quota> <period>"` in microseconds, `"50000 100000"` is half a CPU
+    pub cpu_max: Option<String>,
+    /// `pids.max`
+    pub pids_max: Option<Limit>,
+}
+
+impl Rlimits {
+    fn resolved(&self) -> Result<Vec<(Resource, u64)>, String> {
+        [
+            (Resource::RLIMIT_NOFILE, "nofile", &self.nofile),
+            (Resource::RLIMIT_NPROC, "nproc", &self.nproc),
+            (Resource::RLIMIT_CORE, "core", &self.core),
+            (Resource::RLIMIT_AS, "as", &self.address_space),
+        ]
+        .into_iter()
+        .filter_map(|(resource, name, limit)| Some((resource, name, limit.as_ref()?)))
+        .map(|(resource, name, limit)| {
+            let value = limit
+                .value()
+                .map_err(|err| format!("rlimit {name}: {err}"))?;
+            Ok((resource, value.unwrap_or(RLIM_INFINITY)))
+        })
+        .collect()
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        self.resolved().map(|_| ())
+    }
+
+    /// Sets the limits in the child between fork and exec. Has to come before the switch to
+    /// another user, raising a hard limit takes root.
+    pub fn apply(&self, command: &mut Command) -> io::Result<()> {
+        let limits = self
+            .resolved()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        unsafe {
+            command.pre_exec(move || {
+                for (resource, value) in &limits {
+                    setrlimit(*resource, *value, *value)?;
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_limits() {
+        assert_eq!(Limit::Number(1024).value(), Ok(Some(1024)));
+        assert_eq!(Limit::Text("512M".to_string()).value(), Ok(Some(512 << 20)));
+        assert_eq!(Limit::Text("2g".to_string()).value(), Ok(Some(2 << 30)));
+        assert_eq!(Limit::Text("unlimited".to_string()).value(), Ok(None));
+        assert!(Limit::Text("lots".to_string()).value().is_err());
+        assert!(Limit::Text("M".to_string()).value().is_err());
+    }
+}
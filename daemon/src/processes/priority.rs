@@ -0,0 +1,320 @@
+use std::fmt::Display;
+
+use nix::{
+    errno::Errno,
+    fcntl::{OFlag, open},
+    libc,
+    sched::{CpuSet, sched_setaffinity},
+    sys::stat::Mode,
+    unistd::{Pid, write},
+};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use super::Ecosystem;
+
+const IOPRIO_CLASS_SHIFT: i32 = 13;
+const IOPRIO_WHO_PROCESS: i32 = 1;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoClass {
+    #[serde(rename = "realtime")]
+    Realtime,
+    #[serde(rename = "best_effort")]
+    BestEffort,
+    #[serde(rename = "idle")]
+    Idle,
+}
+
+impl Display for IoClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Realtime => write!(f, "realtime"),
+            Self::BestEffort => write!(f, "best_effort"),
+            Self::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+/// The I/O scheduling class and level, like `ionice -c <class> -n <level>`.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct IoNice {
+    pub class: IoClass,
+    /// 0 (highest) to 7, unused by the idle class
+    pub level: Option<u8>,
+}
+
+impl Display for IoNice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.level {
+            Some(level) if self.class != IoClass::Idle => write!(f, "{} {}", self.class, level),
+            _ => write!(f, "{}", self.class),
+        }
+    }
+}
+
+impl IoNice {
+    fn ioprio(&self) -> i32 {
+        let (class, default_level) = match self.class {
+            IoClass::Realtime => (1, 4),
+            IoClass::BestEffort => (2, 4),
+            IoClass::Idle => (3, 0),
+        };
+        let level = match self.class {
+            IoClass::Idle => 0,
+            _ => self.level.unwrap_or(default_level),
+        };
+        (class << IOPRIO_CLASS_SHIFT) | i32::from(level)
+    }
+}
+
+/// A CPU number, or a range of them like `"4-7"`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Cpus {
+    One(usize),
+    Range(String),
+}
+
+impl Display for Cpus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::One(cpu) => write!(f, "{cpu}"),
+            Self::Range(range) => write!(f, "{range}"),
+        }
+    }
+}
+
+impl Cpus {
+    fn cpus(&self) -> Result<Vec<usize>, String> {
+        let range = match self {
+            Self::One(cpu) => return Ok(vec![*cpu]),
+            Self::Range(range) => range,
+        };
+        let parse = |cpu: &str| {
+            cpu.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("invalid CPU range \"{range}\""))
+        };
+        match range.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse(first)?, parse(last)?);
+                if first > last {
+                    return Err(format!("invalid CPU range \"{range}\""));
+                }
+                Ok((first..=last).collect())
+            }
+            None => Ok(vec![parse(range)?]),
+        }
+    }
+}
+
+/// Priorities resolved ahead of the fork, the child only makes the syscalls.
+#[derive(Debug, Clone, Copy)]
+pub struct Priority {
+    nice: Option<i32>,
+    ioprio: Option<i32>,
+    affinity: Option<CpuSet>,
+    oom_score_adj: Option<i32>,
+}
+
+impl Ecosystem {
+    pub fn priority(&self) -> Result<Priority, String> {
+        if let Some(nice) = self.nice
+            && !(-20..=19).contains(&nice)
+        {
+            return Err(format!(
+                "nice {nice} is out of range, it goes from -20 to 19"
+            ));
+        }
+        if let Some(level) = self.ionice.and_then(|ionice| ionice.level)
+            && level > 7
+        {
+            return Err(format!(
+                "ionice level {level} is out of range, it goes from 0 to 7"
+            ));
+        }
+        if let Some(adj) = self.oom_score_adj
+            && !(-1000..=1000).contains(&adj)
+        {
+            return Err(format!(
+                "oom_score_adj {adj} is out of range, it goes from -1000 to 1000"
+            ));
+        }
+        let affinity = match &self.cpu_affinity {
+            Some(cpus) => {
+                let mut set = CpuSet::new();
+                for cpus in cpus {
+                    for cpu in cpus.cpus()? {
+                        set.set(cpu)
+                            .map_err(|_| format!("CPU {cpu} is beyond what can be used"))?;
+                    }
+                }
+                Some(set)
+            }
+            None => None,
+        };
+        Ok(Priority {
+            nice: self.nice,
+            ioprio: self.ionice.map(|ionice| ionice.ioprio()),
+            affinity,
+            oom_score_adj: self.oom_score_adj,
+        })
+    }
+}
+
+impl Priority {
+    /// Applies the priorities in the child before it executes. Raising any of them takes
+    /// root, so this has to happen before the switch to another user.
+    pub fn apply(self, command: &mut Command) {
+        if self.nice.is_none()
+            && self.ioprio.is_none()
+            && self.affinity.is_none()
+            && self.oom_score_adj.is_none()
+        {
+            return;
+        }
+        // The value is formatted here, nothing may allocate after the fork
+        let oom_score_adj = self.oom_score_adj.map(|adj| adj.to_string().into_bytes());
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(nice) = self.nice {
+                    Errno::result(libc::setpriority(libc::PRIO_PROCESS, 0, nice))?;
+                }
+                if let Some(ioprio) = self.ioprio {
+                    Errno::result(libc::syscall(
+                        libc::SYS_ioprio_set,
+                        IOPRIO_WHO_PROCESS,
+                        0,
+                        ioprio,
+                    ))?;
+                }
+                if let Some(affinity) = &self.affinity {
+                    sched_setaffinity(Pid::from_raw(0), affinity)?;
+                }
+                if let Some(adj) = &oom_score_adj {
+                    let file = open(c"/proc/self/oom_score_adj", OFlag::O_WRONLY, Mode::empty())?;
+                    write(&file, adj)?;
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(range: &str) -> Cpus {
+        Cpus::Range(range.to_string())
+    }
+
+    #[test]
+    fn parses_cpus() {
+        let cases = [
+            (Cpus::One(3), Ok(vec![3])),
+            (range("4-7"), Ok(vec![4, 5, 6, 7])),
+            (range("2-2"), Ok(vec![2])),
+            (range(" 3 "), Ok(vec![3])),
+            (range(" 0 - 1 "), Ok(vec![0, 1])),
+            (range("7-4"), Err("invalid CPU range \"7-4\"")),
+            (range("four"), Err("invalid CPU range \"four\"")),
+            (range("1-"), Err("invalid CPU range \"1-\"")),
+            (range("-1"), Err("invalid CPU range \"-1\"")),
+            (range(""), Err("invalid CPU range \"\"")),
+        ];
+        for (cpus, expected) in cases {
+            assert_eq!(cpus.cpus(), expected.map_err(str::to_string), "{cpus}");
+        }
+    }
+
+    #[test]
+    fn shifts_the_io_class() {
+        let ionice = |class, level| IoNice { class, level };
+        let cases = [
+            (ionice(IoClass::Realtime, Some(0)), 1 << 13),
+            (ionice(IoClass::Realtime, None), (1 << 13) | 4),
+            (ionice(IoClass::BestEffort, Some(7)), (2 << 13) | 7),
+            (ionice(IoClass::BestEffort, None), (2 << 13) | 4),
+            // The idle class has no levels
+            (ionice(IoClass::Idle, None), 3 << 13),
+            (ionice(IoClass::Idle, Some(5)), 3 << 13),
+        ];
+        for (ionice, expected) in cases {
+            assert_eq!(ionice.ioprio(), expected, "{ionice}");
+        }
+    }
+
+    #[test]
+    fn checks_ranges() {
+        let priority = |ecosystem: Ecosystem| ecosystem.priority().map(|_| ());
+        let nice = |nice| Ecosystem {
+            nice: Some(nice),
+            ..Default::default()
+        };
+        let ionice = |level| Ecosystem {
+            ionice: Some(IoNice {
+                class: IoClass::BestEffort,
+                level: Some(level),
+            }),
+            ..Default::default()
+        };
+        let oom = |adj| Ecosystem {
+            oom_score_adj: Some(adj),
+            ..Default::default()
+        };
+        let cases = [
+            (nice(-20), Ok(())),
+            (nice(19), Ok(())),
+            (
+                nice(-21),
+                Err("nice -21 is out of range, it goes from -20 to 19"),
+            ),
+            (
+                nice(20),
+                Err("nice 20 is out of range, it goes from -20 to 19"),
+            ),
+            (ionice(7), Ok(())),
+            (
+                ionice(8),
+                Err("ionice level 8 is out of range, it goes from 0 to 7"),
+            ),
+            (oom(-1000), Ok(())),
+            (oom(1000), Ok(())),
+            (
+                oom(1001),
+                Err("oom_score_adj 1001 is out of range, it goes from -1000 to 1000"),
+            ),
+            (
+                oom(-1001),
+                Err("oom_score_adj -1001 is out of range, it goes from -1000 to 1000"),
+            ),
+        ];
+        for (ecosystem, expected) in cases {
+            assert_eq!(priority(ecosystem), expected.map_err(str::to_string));
+        }
+    }
+
+    #[test]
+    fn builds_the_cpu_set() {
+        let affinity = |cpus| Ecosystem {
+            cpu_affinity: Some(cpus),
+            ..Default::default()
+        };
+        let set = affinity(vec![Cpus::One(0), range("2-3")])
+            .priority()
+            .unwrap()
+            .affinity
+            .unwrap();
+        for (cpu, expected) in [(0, true), (1, false), (2, true), (3, true), (4, false)] {
+            assert_eq!(set.is_set(cpu), Ok(expected), "CPU {cpu}");
+        }
+
+        let beyond = CpuSet::count();
+        let err = affinity(vec![Cpus::One(beyond)]).priority().unwrap_err();
+        assert_eq!(err, format!("CPU {beyond} is beyond what can be used"));
+        let err = affinity(vec![range("9-8")]).priority().unwrap_err();
+        assert_eq!(err, "invalid CPU range \"9-8\"");
+    }
+}
@@ -0,0 +1,129 @@
+use std::{collections::HashSet, time::Duration};
+
+use procfs::{
+    net::TcpState,
+    process::{FDTarget, Process as ProcProcess},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{process::Command, time::Instant};
+
+use super::{ID_ENV, INSTANCE_ENV, Process, ProcessStatus};
+
+const DEFAULT_READY_TIMEOUT: u64 = 30;
+const DEFAULT_READY_DELAY: u64 = 1;
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// When a freshly started child counts as ready to take over from the old one.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ReadyCheck {
+    /// A port the child or one of its descendants has to listen on. Offset by the instance
+    /// number, like `port`
+    pub port: Option<u16>,
+    /// A command run in the process directory that has to exit with 0
+    pub command: Option<String>,
+    /// Seconds the child has to keep running before it is checked
+    pub delay: Option<u64>,
+    /// Seconds to wait for the child to become ready
+    pub timeout: Option<u64>,
+}
+
+impl Process {
+    /// Waits until the current child passes the readiness check of its ecosystem.
+    pub async fn wait_ready(&self) -> Result<(), String> {
+        let check = self.ecosystem.ready.clone().unwrap_or_default();
+        let timeout = Duration::from_secs(check.timeout.unwrap_or(DEFAULT_READY_TIMEOUT));
+        let delay = Duration::from_secs(check.delay.unwrap_or(DEFAULT_READY_DELAY));
+        let deadline = Instant::now() + timeout;
+        let port = match check.port {
+            Some(port) => Some(
+                u16::try_from(self.instance)
+                    .ok()
+                    .and_then(|instance| port.checked_add(instance))
+                    .ok_or("the readiness port is out of range")?,
+            ),
+            None => None,
+        };
+
+        let mut running_since: Option<Instant> = None;
+        let mut started = false;
+        loop {
+            match self.status() {
+                // The supervisor may not have picked up the start yet
+                ProcessStatus::Stopped if !started => {}
+                ProcessStatus::Starting => started = true,
+                ProcessStatus::Running => {
+                    started = true;
+                    let since = *running_since.get_or_insert_with(Instant::now);
+                    if since.elapsed() >= delay && self.passes(&check, port).await {
+                        return Ok(());
+                    }
+                }
+                status => return Err(format!("it went {status} before it was ready")),
+            }
+            if Instant::now() >= deadline {
+                return Err(format!("it wasn't ready after {}s", timeout.as_secs()));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    async fn passes(&self, check: &ReadyCheck, port: Option<u16>) -> bool {
+        if let Some(port) = port {
+            let pids = {
+                let state = self.state.lock().await;
+                let mut pids: Vec<u32> = state.descendants.keys().copied().collect();
+                pids.extend(state.uid);
+                pids
+            };
+            if !listens_on(&pids, port) {
+                return false;
+            }
+        }
+        if let Some(command) = &check.command {
+            let mut ready = Command::new(self.ecosystem.shell());
+            ready
+                .current_dir(&self.path)
+                .arg("-c")
+                .arg(command)
+                .env(ID_ENV, self.id.to_string())
+                .env(INSTANCE_ENV, self.instance.to_string())
+                .kill_on_drop(true);
+            // Checked as the same user the process runs as
+            match self.ecosystem.credentials() {
+                Ok(Some(credentials)) => {
+                    ready.envs(credentials.vars());
+                    credentials.apply(&mut ready);
+                }
+                Ok(None) => {}
+                Err(_) => return false,
+            }
+            let status = ready.status().await;
+            if !status.is_ok_and(|status| status.success()) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Whether one of `pids` holds a socket listening on `port`. Looking at the owner matters
+/// during a reload, the old child may well be listening on the same port.
+fn listens_on(pids: &[u32], port: u16) -> bool {
+    let inodes: HashSet<u64> = [procfs::net::tcp(), procfs::net::tcp6()]
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.state == TcpState::Listen && entry.local_address.port() == port)
+        .map(|entry| entry.inode)
+        .collect();
+    if inodes.is_empty() {
+        return false;
+    }
+    pids.iter().any(|pid| {
+        let Ok(fds) = ProcProcess::new(*pid as i32).and_then(|process| process.fd()) else {
+            return false;
+        };
+        fds.flatten()
+            .any(|fd| matches!(fd.target, FDTarget::Socket(inode) if inodes.contains(&inode)))
+    })
+}
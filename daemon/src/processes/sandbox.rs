@@ -0,0 +1,434 @@
+use std::{
+    ffi::CString,
+    fmt::Display,
+    os::unix::{ffi::OsStrExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        OnceLock,
+        atomic::{AtomicI32, Ordering},
+    },
+};
+
+use nix::{
+    libc,
+    mount::{MsFlags, mount},
+    sched::{CloneFlags, unshare},
+    sys::{
+        prctl,
+        signal::{SigHandler, Signal, kill, signal},
+        wait::{WaitStatus, waitpid},
+    },
+    unistd::{ForkResult, fork, getpid},
+};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use super::Ecosystem;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Namespace {
+    #[serde(rename = "mount")]
+    Mount,
+    #[serde(rename = "pid")]
+    Pid,
+}
+
+impl Display for Namespace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mount => write!(f, "mount"),
+            Self::Pid => write!(f, "pid"),
+        }
+    }
+}
+
+/// Hardening applied to the child before it executes. Whatever the kernel or the daemon's
+/// privileges don't allow is skipped with a warning instead of failing the start.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct Sandbox {
+    /// Neither the child nor anything it runs can gain privileges, setuid binaries included
+    pub no_new_privs: Option<bool>,
+    /// An empty `/tmp` only this process sees
+    pub private_tmp: Option<bool>,
+    /// Paths, relative to the process directory or absolute, the process can't write to
+    pub read_only_paths: Option<Vec<String>>,
+    /// Namespaces of its own, `"mount"` and `"pid"`. The other settings but `no_new_privs`
+    /// imply a mount namespace
+    pub unshare: Option<Vec<Namespace>>,
+}
+
+impl Display for Sandbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+        if self.no_new_privs == Some(true) {
+            parts.push("no_new_privs".to_string());
+        }
+        if self.private_tmp == Some(true) {
+            parts.push("private /tmp".to_string());
+        }
+        if let Some(paths) = &self.read_only_paths
+            && !paths.is_empty()
+        {
+            parts.push(format!("read-only {}", paths.join(", ")));
+        }
+        for namespace in self.unshare.iter().flatten() {
+            parts.push(format!("{namespace} namespace"));
+        }
+        match parts.is_empty() {
+            true => write!(f, "none"),
+            false => write!(f, "{}", parts.join(", ")),
+        }
+    }
+}
+
+/// The sandbox as it will be applied, with every path prepared ahead of the fork.
+#[derive(Debug, Default)]
+pub struct SandboxPlan {
+    no_new_privs: bool,
+    mount_ns: bool,
+    pid_ns: bool,
+    private_tmp: bool,
+    read_only: Vec<CString>,
+}
+
+/// Whether the daemon can create a namespace of the kind, found out once by trying.
+fn supported(flags: CloneFlags) -> bool {
+    static MOUNT: OnceLock<bool> = OnceLock::new();
+    static PID: OnceLock<bool> = OnceLock::new();
+    let probe = || {
+        let mut probe = std::process::Command::new("/bin/sh");
+        probe
+            .arg("-c")
+            .arg(":")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        unsafe {
+            probe.pre_exec(move || Ok(unshare(flags)?));
+        }
+        probe.status().is_ok_and(|status| status.success())
+    };
+    if flags == CloneFlags::CLONE_NEWPID {
+        *PID.get_or_init(probe)
+    } else {
+        *MOUNT.get_or_init(probe)
+    }
+}
+
+impl Ecosystem {
+    /// What of the sandbox can be applied, and a warning for each part that can't.
+    pub fn sandbox_plan(&self, dir: &str) -> (SandboxPlan, Vec<String>) {
+        self.plan_sandbox(dir, supported)
+    }
+
+    /// [`Self::sandbox_plan`] with the namespaces `supported` says the daemon can create.
+    fn plan_sandbox(
+        &self,
+        dir: &str,
+        supported: impl Fn(CloneFlags) -> bool,
+    ) -> (SandboxPlan, Vec<String>) {
+        let mut warnings = vec![];
+        let Some(sandbox) = &self.sandbox else {
+            return (SandboxPlan::default(), warnings);
+        };
+        let unshare = sandbox.unshare.clone().unwrap_or_default();
+        let mut plan = SandboxPlan {
+            no_new_privs: sandbox.no_new_privs == Some(true),
+            mount_ns: unshare.contains(&Namespace::Mount),
+            pid_ns: unshare.contains(&Namespace::Pid),
+            private_tmp: sandbox.private_tmp == Some(true),
+            read_only: vec![],
+        };
+        for path in sandbox.read_only_paths.iter().flatten() {
+            let path: PathBuf = Path::new(dir).join(path);
+            match path.canonicalize() {
+                Ok(path) => match CString::new(path.as_os_str().as_bytes()) {
+                    Ok(path) => plan.read_only.push(path),
+                    Err(_) => warnings.push(format!("{} isn't a valid path", path.display())),
+                },
+                Err(err) => {
+                    warnings.push(format!("{} can't be made read-only: {err}", path.display()))
+                }
+            }
+        }
+        plan.mount_ns |= plan.private_tmp || !plan.read_only.is_empty();
+
+        if plan.mount_ns && !supported(CloneFlags::CLONE_NEWNS) {
+            warnings.push(
+                "mount namespaces aren't available to fpmd, running without private /tmp \
+                 and read-only paths"
+                    .to_string(),
+            );
+            plan.mount_ns = false;
+            plan.private_tmp = false;
+            plan.read_only.clear();
+        }
+        if plan.pid_ns && !supported(CloneFlags::CLONE_NEWPID) {
+            warnings.push(
+                "pid namespaces aren't available to fpmd, running in the daemon's".to_string(),
+            );
+            plan.pid_ns = false;
+        }
+        (plan, warnings)
+    }
+}
+
+/// The child in the new pid namespace, signals sent to its parent are passed on to it.
+static INNER: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn forward(signal: libc::c_int) {
+    unsafe {
+        libc::kill(INNER.load(Ordering::Relaxed), signal);
+    }
+}
+
+impl SandboxPlan {
+    /// Applies the sandbox between fork and exec. Has to come before the switch to another
+    /// user, mounting takes root.
+    pub fn apply(self, command: &mut Command) {
+        if !self.no_new_privs && !self.mount_ns && !self.pid_ns {
+            return;
+        }
+        unsafe {
+            command.pre_exec(move || {
+                let mut flags = CloneFlags::empty();
+                if self.mount_ns {
+                    flags |= CloneFlags::CLONE_NEWNS;
+                }
+                if self.pid_ns {
+                    flags |= CloneFlags::CLONE_NEWPID;
+                }
+                if !flags.is_empty() {
+                    unshare(flags)?;
+                }
+                if self.mount_ns {
+                    self.mount()?;
+                }
+                if self.no_new_privs {
+                    prctl::set_no_new_privs()?;
+                }
+                if self.pid_ns {
+                    enter_pid_namespace(self.mount_ns)?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    fn mount(&self) -> nix::Result<()> {
+        const NONE: Option<&str> = None;
+        // Nothing mounted here may leak back into the daemon's namespace
+        mount(NONE, "/", NONE, MsFlags::MS_REC | MsFlags::MS_PRIVATE, NONE)?;
+        for path in &self.read_only {
+            let path = path.as_c_str();
+            mount(
+                Some(path),
+                path,
+                NONE,
+                MsFlags::MS_BIND | MsFlags::MS_REC,
+                NONE,
+            )?;
+            mount(
+                NONE,
+                path,
+                NONE,
+                MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_RDONLY,
+                NONE,
+            )?;
+        }
+        if self.private_tmp {
+            mount(
+                Some("tmpfs"),
+                "/tmp",
+                Some("tmpfs"),
+                MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+                Some("mode=1777"),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Only children end up in a new pid namespace, so this forks once more. The new child
+/// goes on to exec as pid 1 of the namespace, while this process stays behind to relay
+/// signals to it and exit the way it does.
+fn enter_pid_namespace(mount_proc: bool) -> nix::Result<()> {
+    match unsafe { fork() }? {
+        ForkResult::Child => {
+            prctl::set_pdeathsig(Signal::SIGKILL)?;
+            if mount_proc {
+                const NONE: Option<&str> = None;
+                mount(
+                    Some("proc"),
+                    "/proc",
+                    Some("proc"),
+                    MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+                    NONE,
+                )?;
+            }
+            Ok(())
+        }
+        ForkResult::Parent { child } => {
+            INNER.store(child.as_raw(), Ordering::Relaxed);
+            for relayed in [
+                Signal::SIGTERM,
+                Signal::SIGINT,
+                Signal::SIGHUP,
+                Signal::SIGQUIT,
+                Signal::SIGUSR1,
+                Signal::SIGUSR2,
+            ] {
+                unsafe {
+                    let _ = signal(relayed, SigHandler::Handler(forward));
+                }
+            }
+            // Closes the pipe the spawning side waits on, so it doesn't wait for us to exit
+            unsafe {
+                libc::syscall(libc::SYS_close_range, 3, libc::c_uint::MAX, 0);
+            }
+            loop {
+                match waitpid(child, None) {
+                    Ok(WaitStatus::Exited(_, code)) => unsafe { libc::_exit(code) },
+                    Ok(WaitStatus::Signaled(_, signaled, _)) => unsafe {
+                        let _ = signal(signaled, SigHandler::SigDfl);
+                        let _ = kill(getpid(), signaled);
+                        libc::_exit(128 + signaled as i32)
+                    },
+                    Ok(_) | Err(nix::errno::Errno::EINTR) => continue,
+                    Err(_) => unsafe { libc::_exit(1) },
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn ecosystem(sandbox: Sandbox) -> Ecosystem {
+        Ecosystem {
+            sandbox: Some(sandbox),
+            ..Default::default()
+        }
+    }
+
+    fn everything(_: CloneFlags) -> bool {
+        true
+    }
+
+    fn paths(paths: &[&str]) -> Option<Vec<String>> {
+        Some(paths.iter().map(|path| path.to_string()).collect())
+    }
+
+    #[test]
+    fn plans_nothing_without_a_sandbox() {
+        let (plan, warnings) = Ecosystem::default().plan_sandbox("/", |_| unreachable!());
+        assert!(!plan.no_new_privs && !plan.mount_ns && !plan.pid_ns && !plan.private_tmp);
+        assert!(warnings.is_empty());
+
+        // Needs no namespace, so nothing is probed
+        let sandbox = Sandbox {
+            no_new_privs: Some(true),
+            ..Default::default()
+        };
+        let (plan, warnings) = ecosystem(sandbox).plan_sandbox("/", |_| unreachable!());
+        assert!(plan.no_new_privs && !plan.mount_ns);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn mounts_imply_a_mount_namespace() {
+        let sandbox = Sandbox {
+            private_tmp: Some(true),
+            ..Default::default()
+        };
+        let (plan, _) = ecosystem(sandbox).plan_sandbox("/", everything);
+        assert!(plan.mount_ns && plan.private_tmp && !plan.pid_ns);
+
+        let sandbox = Sandbox {
+            unshare: Some(vec![Namespace::Pid]),
+            ..Default::default()
+        };
+        let (plan, _) = ecosystem(sandbox).plan_sandbox("/", everything);
+        assert!(plan.pid_ns && !plan.mount_ns);
+    }
+
+    #[test]
+    fn resolves_read_only_paths() {
+        let dir = std::env::temp_dir().join(format!("fpm-sandbox-{}", std::process::id()));
+        fs::create_dir_all(dir.join("config")).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let sandbox = Sandbox {
+            read_only_paths: paths(&["config", "./config/../config", "/etc", "missing"]),
+            ..Default::default()
+        };
+        let (plan, warnings) = ecosystem(sandbox).plan_sandbox(&dir.to_string_lossy(), everything);
+        let config = CString::new(dir.join("config").as_os_str().as_bytes()).unwrap();
+        let etc = CString::new(
+            Path::new("/etc")
+                .canonicalize()
+                .unwrap()
+                .as_os_str()
+                .as_bytes(),
+        );
+        assert_eq!(plan.read_only, [config.clone(), config, etc.unwrap()]);
+        assert!(plan.mount_ns);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with(&format!(
+            "{} can't be made read-only: ",
+            dir.join("missing").display()
+        )));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn skips_what_the_daemon_cant_do() {
+        let sandbox = Sandbox {
+            no_new_privs: Some(true),
+            private_tmp: Some(true),
+            read_only_paths: paths(&["/"]),
+            unshare: Some(vec![Namespace::Mount, Namespace::Pid]),
+        };
+        let ecosystem = ecosystem(sandbox);
+
+        let (plan, warnings) = ecosystem.plan_sandbox("/", |_| false);
+        assert!(plan.no_new_privs);
+        assert!(!plan.mount_ns && !plan.pid_ns && !plan.private_tmp);
+        assert!(plan.read_only.is_empty());
+        assert_eq!(
+            warnings,
+            [
+                "mount namespaces aren't available to fpmd, running without private /tmp and \
+                 read-only paths",
+                "pid namespaces aren't available to fpmd, running in the daemon's",
+            ]
+        );
+
+        let (plan, warnings) =
+            ecosystem.plan_sandbox("/", |flags| flags == CloneFlags::CLONE_NEWNS);
+        assert!(plan.mount_ns && plan.private_tmp && !plan.pid_ns);
+        assert_eq!(plan.read_only.len(), 1);
+        assert_eq!(
+            warnings,
+            ["pid namespaces aren't available to fpmd, running in the daemon's"]
+        );
+    }
+
+    #[test]
+    fn describes_the_sandbox() {
+        assert_eq!(Sandbox::default().to_string(), "none");
+        let sandbox = Sandbox {
+            no_new_privs: Some(true),
+            private_tmp: Some(false),
+            read_only_paths: paths(&["config", "/etc"]),
+            unshare: Some(vec![Namespace::Pid]),
+        };
+        assert_eq!(
+            sandbox.to_string(),
+            "no_new_privs, read-only config, /etc, pid namespace"
+        );
+    }
+}
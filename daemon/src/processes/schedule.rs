@@ -0,0 +1,77 @@
+use std::{pin::Pin, str::FromStr};
+
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+use cron::Schedule;
+use tokio::time::Sleep;
+
+use super::{Ecosystem, ExitReason};
+
+/// One finished run of a job.
+#[derive(Debug, Clone)]
+pub struct JobRun {
+    pub started_at: DateTime<Utc>,
+    pub duration: chrono::Duration,
+    pub exit: Option<ExitReason>,
+}
+
+/// Parses a cron expression. The usual five fields are accepted as well as the six and seven
+/// field forms with seconds and years.
+fn parse(expression: &str) -> Result<Schedule, String> {
+    let expression = expression.trim();
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {expression}")
+    } else {
+        expression.to_string()
+    };
+    Schedule::from_str(&expression).map_err(|err| format!("invalid cron expression: {err}"))
+}
+
+fn timezone(name: &str) -> Result<Tz, String> {
+    name.parse::<Tz>()
+        .map_err(|_| format!("unknown timezone \"{name}\""))
+}
+
+impl Ecosystem {
+    /// The expression that drives the process: when a job runs, or when a service restarts.
+    fn cron(&self) -> Option<&str> {
+        if self.is_job() {
+            self.schedule.as_deref()
+        } else {
+            self.cron_restart.as_deref()
+        }
+    }
+
+    /// Makes sure the schedule settings make sense before the process is created.
+    pub fn check_schedule(&self) -> Result<(), String> {
+        if self.is_job() && self.schedule.is_none() {
+            return Err(format!("{} is a job but has no schedule", self.title));
+        }
+        if let Some(expression) = self.cron() {
+            parse(expression).map_err(|err| format!("{}: {err}", self.title))?;
+        }
+        if let Some(name) = &self.timezone {
+            timezone(name).map_err(|err| format!("{}: {err}", self.title))?;
+        }
+        Ok(())
+    }
+
+    /// When the schedule fires next.
+    pub fn next_run(&self) -> Option<DateTime<Utc>> {
+        let schedule = parse(self.cron()?).ok()?;
+        let next = match &self.timezone {
+            Some(name) => schedule
+                .upcoming(timezone(name).ok()?)
+                .next()?
+                .with_timezone(&Utc),
+            None => schedule.upcoming(Local).next()?.with_timezone(&Utc),
+        };
+        Some(next)
+    }
+
+    /// A timer for the next time the schedule fires, if there is a schedule.
+    pub fn next_tick(&self) -> Option<Pin<Box<Sleep>>> {
+        let delay = (self.next_run()? - Utc::now()).to_std().unwrap_or_default();
+        Some(Box::pin(tokio::time::sleep(delay)))
+    }
+}
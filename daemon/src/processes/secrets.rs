@@ -0,0 +1,162 @@
+use std::{
+    fs, io,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::Path,
+};
+
+use nix::unistd::geteuid;
+
+use super::Ecosystem;
+
+/// Shown instead of the value of a secret.
+pub const REDACTED: &str = "<redacted>";
+
+fn error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, message)
+}
+
+impl Ecosystem {
+    /// `env` followed by `env_secrets` with every secret read from its file. The files are
+    /// read at each start, so a restart picks up a rotated secret.
+    pub fn env_vars(&self, dir: &str) -> io::Result<Vec<(&str, String)>> {
+        let mut vars: Vec<(&str, String)> = self
+            .env
+            .iter()
+            .flatten()
+            .map(|(name, value)| (name.as_str(), value.clone()))
+            .collect();
+        for (name, path) in self.env_secrets.iter().flatten() {
+            let value = self
+                .read_secret(&Path::new(dir).join(path))
+                .map_err(|err| io::Error::new(err.kind(), format!("secret {name}: {err}")))?;
+            vars.push((name.as_str(), value));
+        }
+        Ok(vars)
+    }
+
+    /// Reads a secret file, which only its owner may access. The owner has to be the daemon's
+    /// user, root or the user the process runs as, nobody else could have put it there.
+    fn read_secret(&self, path: &Path) -> io::Result<String> {
+        let metadata = fs::metadata(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.display())))?;
+        if !metadata.is_file() {
+            return Err(error(format!("{} isn't a file", path.display())));
+        }
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Err(error(format!(
+                "{} has mode {mode:o}, only its owner may access it, chmod 600 it",
+                path.display()
+            )));
+        }
+        let owner = metadata.uid();
+        let user = self
+            .credentials()?
+            .map(|credentials| credentials.user.uid.as_raw());
+        if owner != 0 && owner != geteuid().as_raw() && Some(owner) != user {
+            return Err(error(format!(
+                "{} belongs to uid {owner}, not to fpmd or the process' user",
+                path.display()
+            )));
+        }
+        let value = fs::read_to_string(path)?;
+        // Files written by editors or `echo` end in a newline that isn't part of the secret
+        let value = value.strip_suffix('\n').unwrap_or(&value);
+        Ok(value.strip_suffix('\r').unwrap_or(value).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, os::unix::fs::chown, path::PathBuf};
+
+    use super::*;
+
+    /// A directory of its own for each test, they run in parallel.
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fpm-secrets-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn secret(dir: &Path, name: &str, value: &str, mode: u32) -> String {
+        let path = dir.join(name);
+        fs::write(&path, value).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn ecosystem(name: &str, path: String) -> Ecosystem {
+        Ecosystem {
+            env_secrets: Some(BTreeMap::from([(name.to_string(), path)])),
+            ..Default::default()
+        }
+    }
+
+    fn read(ecosystem: &Ecosystem, dir: &Path) -> io::Result<Vec<(String, String)>> {
+        let vars = ecosystem.env_vars(&dir.to_string_lossy())?;
+        Ok(vars
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect())
+    }
+
+    #[test]
+    fn reads_owner_only_secrets() {
+        let dir = dir("owner-only");
+        let path = secret(&dir, "db", "hunter2\n", 0o600);
+        let vars = read(&ecosystem("DB_PASS", path), &dir).unwrap();
+        assert_eq!(vars, [("DB_PASS".to_string(), "hunter2".to_string())]);
+
+        // Relative to the process directory
+        let vars = read(&ecosystem("DB_PASS", "db".to_string()), &dir).unwrap();
+        assert_eq!(vars[0].1, "hunter2");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_secrets_others_can_read() {
+        let dir = dir("readable");
+        for mode in [0o640, 0o644, 0o604] {
+            let path = secret(&dir, "db", "hunter2", mode);
+            let err = read(&ecosystem("DB_PASS", path), &dir).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied, "mode {mode:o}");
+            assert!(err.to_string().contains(&format!("mode {mode:o}")));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_missing_secrets() {
+        let dir = dir("missing");
+        let err = read(&ecosystem("DB_PASS", "nope".to_string()), &dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().starts_with("secret DB_PASS: "));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_secrets_of_other_users() {
+        // Only root can give a file away
+        if !geteuid().is_root() {
+            return;
+        }
+        let Ok(Some(nobody)) = nix::unistd::User::from_name("nobody") else {
+            return;
+        };
+        let dir = dir("foreign");
+        let path = secret(&dir, "db", "hunter2", 0o600);
+        chown(&path, Some(nobody.uid.as_raw()), None).unwrap();
+
+        let err = read(&ecosystem("DB_PASS", path.clone()), &dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(err.to_string().contains("belongs to uid"));
+
+        // The user the process runs as may own its secrets
+        let mut ecosystem = ecosystem("DB_PASS", path);
+        ecosystem.user = Some("nobody".to_string());
+        assert_eq!(read(&ecosystem, &dir).unwrap()[0].1, "hunter2");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
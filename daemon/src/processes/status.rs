@@ -0,0 +1,308 @@
+use std::fmt::Display;
+
+use nix::sys::signal::Signal;
+
+use tokio::sync::OwnedSemaphorePermit;
+
+use crate::children::ChildRecord;
+
+/// Where a process is in its lifecycle. States only change through [`ProcessStatus::next`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessStatus {
+    /// Waiting for a free slot in the task queue
+    Queued,
+    Starting,
+    Running,
+    Stopping,
+    Stopped,
+    /// The child exited on its own, with its code when it could be collected
+    Exited(Option<i32>),
+    /// The child was killed by this signal without being asked to stop
+    Crashed(i32),
+    /// Waiting before the next restart
+    Backoff,
+    /// The child could not be spawned at all
+    Errored,
+}
+
+/// Something that happened to a process, fed to [`ProcessStatus::next`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessEvent {
+    /// A start was requested or the backoff ran out
+    Start,
+    /// A task has to wait for a free slot before it can start
+    Queue,
+    Spawned,
+    SpawnFailed,
+    Exited(Option<i32>),
+    Signaled(i32),
+    Stop,
+    /// The restart policy wants another run
+    Backoff,
+}
+
+/// How the last child went away.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitReason {
+    Code(i32),
+    Signal(i32),
+    /// An adopted child that isn't ours to reap, its status can't be collected
+    Unknown,
+}
+
+impl ExitReason {
+    pub fn from_event(event: ProcessEvent) -> Option<Self> {
+        match event {
+            ProcessEvent::Exited(Some(code)) => Some(Self::Code(code)),
+            ProcessEvent::Exited(None) => Some(Self::Unknown),
+            ProcessEvent::Signaled(signal) => Some(Self::Signal(signal)),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ExitReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Code(code) => write!(f, "code {code}"),
+            Self::Signal(signal) => match Signal::try_from(*signal) {
+                Ok(signal) => write!(f, "{signal}"),
+                Err(_) => write!(f, "signal {signal}"),
+            },
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// What the supervisor of a process can be asked to do.
+#[derive(Debug)]
+pub enum ProcessCommand {
+    Start,
+    Stop,
+    Restart,
+    /// A watched file changed, restarts the process only if it is running
+    FilesChanged,
+    /// Supervise a child left running by a previous daemon
+    Adopt(ChildRecord),
+    /// Stop and end the supervisor, the process is gone for good
+    Delete,
+    /// A queued task got a slot to run in, held until the run ends
+    Run(OwnedSemaphorePermit),
+}
+
+impl ProcessStatus {
+    /// The state `event` leads to, or `None` if it makes no sense in the current one.
+    pub fn next(self, event: ProcessEvent) -> Option<Self> {
+        use ProcessEvent as Event;
+        use ProcessStatus as Status;
+        match (self, event) {
+            (
+                Status::Stopped
+                | Status::Exited(_)
+                | Status::Crashed(_)
+                | Status::Backoff
+                | Status::Errored
+                | Status::Queued,
+                Event::Start,
+            ) => Some(Status::Starting),
+            (
+                Status::Stopped
+                | Status::Exited(_)
+                | Status::Crashed(_)
+                | Status::Backoff
+                | Status::Errored,
+                Event::Queue,
+            ) => Some(Status::Queued),
+            (Status::Starting, Event::Spawned) => Some(Status::Running),
+            (Status::Starting, Event::SpawnFailed) => Some(Status::Errored),
+            (Status::Running, Event::Exited(code)) => Some(Status::Exited(code)),
+            (Status::Running, Event::Signaled(signal)) => Some(Status::Crashed(signal)),
+            (Status::Starting | Status::Running, Event::Stop) => Some(Status::Stopping),
+            (Status::Stopping, Event::Exited(_) | Event::Signaled(_)) => Some(Status::Stopped),
+            (
+                Status::Exited(_)
+                | Status::Crashed(_)
+                | Status::Backoff
+                | Status::Errored
+                | Status::Queued,
+                Event::Stop,
+            ) => Some(Status::Stopped),
+            (Status::Exited(_) | Status::Crashed(_), Event::Backoff) => Some(Status::Backoff),
+            _ => None,
+        }
+    }
+
+    /// Whether there is a child, or one is on its way
+    pub fn is_active(self) -> bool {
+        matches!(
+            self,
+            Self::Queued | Self::Starting | Self::Running | Self::Stopping
+        )
+    }
+}
+
+impl Display for ProcessStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Queued => write!(f, "Queued"),
+            Self::Starting => write!(f, "Starting"),
+            Self::Running => write!(f, "Running"),
+            Self::Stopping => write!(f, "Stopping"),
+            Self::Stopped => write!(f, "Stopped"),
+            Self::Exited(Some(code)) => write!(f, "Exited ({})", ExitReason::Code(*code)),
+            Self::Exited(None) => write!(f, "Exited"),
+            Self::Crashed(signal) => write!(f, "Crashed ({})", ExitReason::Signal(*signal)),
+            Self::Backoff => write!(f, "Backoff"),
+            Self::Errored => write!(f, "Errored"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProcessEvent as Event, ProcessStatus as Status};
+
+    const ALL_EVENTS: [Event; 9] = [
+        Event::Start,
+        Event::Queue,
+        Event::Spawned,
+        Event::SpawnFailed,
+        Event::Exited(Some(0)),
+        Event::Exited(None),
+        Event::Signaled(9),
+        Event::Stop,
+        Event::Backoff,
+    ];
+
+    fn assert_only(status: Status, allowed: &[(Event, Status)]) {
+        for event in ALL_EVENTS {
+            let expected = allowed
+                .iter()
+                .find(|(allowed, _)| *allowed == event)
+                .map(|(_, next)| *next);
+            assert_eq!(status.next(event), expected, "{status:?} on {event:?}");
+        }
+    }
+
+    #[test]
+    fn starting() {
+        assert_only(
+            Status::Starting,
+            &[
+                (Event::Spawned, Status::Running),
+                (Event::SpawnFailed, Status::Errored),
+                (Event::Stop, Status::Stopping),
+            ],
+        );
+    }
+
+    #[test]
+    fn running() {
+        assert_only(
+            Status::Running,
+            &[
+                (Event::Exited(Some(0)), Status::Exited(Some(0))),
+                (Event::Exited(None), Status::Exited(None)),
+                (Event::Signaled(9), Status::Crashed(9)),
+                (Event::Stop, Status::Stopping),
+            ],
+        );
+    }
+
+    #[test]
+    fn stopping() {
+        assert_only(
+            Status::Stopping,
+            &[
+                (Event::Exited(Some(0)), Status::Stopped),
+                (Event::Exited(None), Status::Stopped),
+                (Event::Signaled(9), Status::Stopped),
+            ],
+        );
+    }
+
+    #[test]
+    fn stopped() {
+        assert_only(
+            Status::Stopped,
+            &[
+                (Event::Start, Status::Starting),
+                (Event::Queue, Status::Queued),
+            ],
+        );
+    }
+
+    #[test]
+    fn queued() {
+        assert_only(
+            Status::Queued,
+            &[
+                (Event::Start, Status::Starting),
+                (Event::Stop, Status::Stopped),
+            ],
+        );
+    }
+
+    #[test]
+    fn exited() {
+        assert_only(
+            Status::Exited(Some(1)),
+            &[
+                (Event::Start, Status::Starting),
+                (Event::Queue, Status::Queued),
+                (Event::Stop, Status::Stopped),
+                (Event::Backoff, Status::Backoff),
+            ],
+        );
+    }
+
+    #[test]
+    fn crashed() {
+        assert_only(
+            Status::Crashed(11),
+            &[
+                (Event::Start, Status::Starting),
+                (Event::Queue, Status::Queued),
+                (Event::Stop, Status::Stopped),
+                (Event::Backoff, Status::Backoff),
+            ],
+        );
+    }
+
+    #[test]
+    fn backoff() {
+        assert_only(
+            Status::Backoff,
+            &[
+                (Event::Start, Status::Starting),
+                (Event::Queue, Status::Queued),
+                (Event::Stop, Status::Stopped),
+            ],
+        );
+    }
+
+    #[test]
+    fn errored() {
+        assert_only(
+            Status::Errored,
+            &[
+                (Event::Start, Status::Starting),
+                (Event::Queue, Status::Queued),
+                (Event::Stop, Status::Stopped),
+            ],
+        );
+    }
+
+    #[test]
+    fn active_states() {
+        assert!(Status::Queued.is_active());
+        assert!(Status::Starting.is_active());
+        assert!(Status::Running.is_active());
+        assert!(Status::Stopping.is_active());
+        assert!(!Status::Stopped.is_active());
+        assert!(!Status::Exited(Some(0)).is_active());
+        assert!(!Status::Crashed(9).is_active());
+        assert!(!Status::Backoff.is_active());
+        assert!(!Status::Errored.is_active());
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::{Arc, OnceLock};
+
+use nix::{
+    sys::signal::{Signal, kill},
+    unistd::Pid,
+};
+use tokio::{
+    sync::{Mutex, Semaphore},
+    task::JoinHandle,
+    time::Duration,
+};
+
+use crate::cgroups::Cgroup;
+
+use super::{ChildExit, DEFAULT_STOP_TIMEOUT, Process, ProcessCommand, ProcessEvent, ProcessState};
+
+pub const DEFAULT_TASK_CONCURRENCY: usize = 4;
+
+/// Slots shared by every task of the daemon, a task holds one for each run.
+static TASK_SLOTS: OnceLock<Arc<Semaphore>> = OnceLock::new();
+
+pub fn set_task_concurrency(limit: usize) {
+    let _ = TASK_SLOTS.set(Arc::new(Semaphore::new(limit.max(1))));
+}
+
+fn task_slots() -> Arc<Semaphore> {
+    TASK_SLOTS
+        .get_or_init(|| Arc::new(Semaphore::new(DEFAULT_TASK_CONCURRENCY)))
+        .clone()
+}
+
+async fn signal_all(state: &Mutex<ProcessState>, signal: Signal) {
+    let state = state.lock().await;
+    for pid in state.uid.iter().chain(state.descendants.keys()) {
+        let _ = kill(Pid::from_raw(*pid as i32), signal);
+    }
+}
+
+impl Process {
+    /// Starts a child, or for a task, gets in the queue for a slot to start one in.
+    pub(super) async fn begin(
+        self: &Arc<Self>,
+        queued: &mut Option<JoinHandle<()>>,
+    ) -> Option<ChildExit> {
+        if !self.ecosystem.is_task() {
+            return self.launch().await;
+        }
+        if self.transition(ProcessEvent::Queue).await {
+            let commands = self.commands.clone();
+            *queued = Some(tokio::spawn(async move {
+                if let Ok(slot) = task_slots().acquire_owned().await {
+                    let _ = commands.send(ProcessCommand::Run(slot));
+                }
+            }));
+        }
+        None
+    }
+
+    /// Kills a run that takes longer than `timeout`, the usual way: SIGTERM first, SIGKILL
+    /// once `stop_timeout` has passed as well. The exit then counts as a failed run.
+    pub(super) fn with_timeout(&self, mut exit: ChildExit, timeout: u64) -> ChildExit {
+        let state = self.state.clone();
+        let cgroup = Cgroup::of(self.id);
+        let grace =
+            Duration::from_secs(self.ecosystem.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT));
+        Box::pin(async move {
+            if let Ok(event) = tokio::time::timeout(Duration::from_secs(timeout), &mut exit).await {
+                return event;
+            }
+            state.lock().await.last_error = Some(format!("timed out after {timeout}s"));
+            signal_all(&state, Signal::SIGTERM).await;
+            if let Ok(event) = tokio::time::timeout(grace, &mut exit).await {
+                return event;
+            }
+            signal_all(&state, Signal::SIGKILL).await;
+            if let Some(cgroup) = cgroup {
+                cgroup.kill();
+            }
+            exit.await
+        })
+    }
+}
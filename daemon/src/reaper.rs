@@ -0,0 +1,138 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use nix::{
+    sys::{
+        prctl,
+        wait::{WaitPidFlag, waitpid},
+    },
+    unistd::{Pid, getpid},
+};
+use procfs::process::all_processes;
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    sync::Mutex,
+};
+
+use crate::{AppState, children, processes::Process};
+
+/// Makes orphans of our children (double-forking daemons and the like) get reparented to us
+/// instead of PID 1, so they can still be tracked, reaped and killed.
+pub fn become_subreaper() {
+    if let Err(err) = prctl::set_child_subreaper(true) {
+        eprintln!("Can't become a child subreaper, orphaned descendants won't be tracked: {err}");
+    }
+}
+
+struct ProcEntry {
+    ppid: u32,
+    start_time: u64,
+    zombie: bool,
+}
+
+fn snapshot() -> HashMap<u32, ProcEntry> {
+    let Ok(processes) = all_processes() else {
+        return HashMap::new();
+    };
+    processes
+        .filter_map(|process| process.ok()?.stat().ok())
+        .map(|stat| {
+            (
+                stat.pid as u32,
+                ProcEntry {
+                    ppid: stat.ppid as u32,
+                    start_time: stat.starttime,
+                    zombie: stat.state == 'Z',
+                },
+            )
+        })
+        .collect()
+}
+
+/// Everything below `roots`, roots excluded.
+fn descendants_of(roots: &[u32], procs: &HashMap<u32, ProcEntry>) -> Vec<u32> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (pid, entry) in procs {
+        children.entry(entry.ppid).or_default().push(*pid);
+    }
+    let mut found = vec![];
+    let mut queue: Vec<u32> = roots.to_vec();
+    while let Some(pid) = queue.pop() {
+        for child in children.get(&pid).into_iter().flatten() {
+            if !found.contains(child) && !roots.contains(child) {
+                found.push(*child);
+                queue.push(*child);
+            }
+        }
+    }
+    found
+}
+
+/// Refreshes the descendants of one process. Descendants that were already known stay
+/// attached to it after their parent exits, which is what keeps orphans associated.
+fn update_descendants(process: &Process, procs: &HashMap<u32, ProcEntry>) {
+    // A busy process (being stopped, for example) is simply refreshed on the next round
+    let Ok(mut state) = process.state.try_lock() else {
+        return;
+    };
+    let alive = |pid: &u32, start_time: u64| {
+        procs
+            .get(pid)
+            .is_some_and(|entry| entry.start_time == start_time && !entry.zombie)
+    };
+    let known: Vec<u32> = state
+        .descendants
+        .iter()
+        .filter(|(pid, start_time)| alive(pid, **start_time))
+        .map(|(pid, _)| *pid)
+        .collect();
+    let mut roots = known.clone();
+    roots.extend(state.uid);
+    let mut descendants = descendants_of(&roots, procs);
+    descendants.extend(known);
+    state.descendants = descendants
+        .into_iter()
+        .filter(|pid| Some(*pid) != state.uid)
+        .filter_map(|pid| {
+            let entry = procs.get(&pid).filter(|entry| !entry.zombie)?;
+            Some((pid, entry.start_time))
+        })
+        .collect();
+}
+
+/// Tracks descendants of every process and reaps zombies reparented to us. Direct children
+/// someone waits on are left alone, and a zombie is only reaped once it has survived a full
+/// round, so a child that exits before its waiter registers it still gets its status.
+pub async fn run(app_state: Arc<Mutex<AppState>>) {
+    let mut sigchld = signal(SignalKind::child()).expect("Can't handle SIGCHLD");
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    let our_pid = getpid().as_raw() as u32;
+    let mut lingering: HashSet<u32> = HashSet::new();
+    loop {
+        tokio::select! {
+            _ = sigchld.recv() => {}
+            _ = interval.tick() => {}
+        }
+        let procs = snapshot();
+        let processes: Vec<Arc<Process>> =
+            app_state.lock().await.processes.values().cloned().collect();
+        for process in &processes {
+            update_descendants(process, &procs);
+        }
+
+        let zombies: HashSet<u32> = procs
+            .iter()
+            .filter(|(pid, entry)| {
+                entry.ppid == our_pid && entry.zombie && !children::is_waited(**pid)
+            })
+            .map(|(pid, _)| *pid)
+            .collect();
+        for pid in zombies.intersection(&lingering) {
+            let _ = waitpid(Pid::from_raw(*pid as i32), Some(WaitPidFlag::WNOHANG));
+        }
+        lingering = zombies;
+    }
+}
@@ -0,0 +1,201 @@
+use std::{error::Error, net::SocketAddr, path::PathBuf, sync::Arc};
+
+use serde::Deserialize;
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+    time::timeout,
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    },
+};
+
+use crate::{
+    AppState,
+    access::{Peer, Scope},
+    socket::{self, CLIENT_TIMEOUT, Response},
+};
+
+/// Shorter tokens are refused, they could be guessed.
+const MIN_TOKEN_LENGTH: usize = 16;
+
+/// The TCP listener for `fpm --host`. The protocol is the one of the socket, behind TLS and
+/// a `Bearer <token>` line.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Tcp {
+    /// Address and port to listen on, like `"0.0.0.0:7070"`
+    pub listen: String,
+    /// PEM certificate chain the listener presents
+    pub cert: PathBuf,
+    /// PEM private key of `cert`
+    pub key: PathBuf,
+    #[serde(default)]
+    pub tokens: Vec<Token>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Token {
+    /// Shown in the audit log instead of the token
+    pub name: String,
+    pub token: String,
+    pub scope: Scope,
+}
+
+fn acceptor(tcp: &Tcp) -> Result<TlsAcceptor, Box<dyn Error>> {
+    let certs = CertificateDer::pem_file_iter(&tcp.cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| format!("can't read {}: {err}", tcp.cert.display()))?;
+    let key = PrivateKeyDer::from_pem_file(&tcp.key)
+        .map_err(|err| format!("can't read {}: {err}", tcp.key.display()))?;
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+pub async fn start_remote(app_state: Arc<Mutex<AppState>>, tcp: Tcp) -> Result<(), Box<dyn Error>> {
+    let acceptor = acceptor(&tcp)?;
+    let tokens = Arc::new(usable_tokens(tcp.tokens)?);
+    let listener = TcpListener::bind(&tcp.listen).await?;
+    println!("Listening for remote clients on {}...", tcp.listen);
+
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let app_state = app_state.clone();
+        let acceptor = acceptor.clone();
+        let tokens = tokens.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_client(app_state, acceptor, &tokens, stream, addr).await {
+                eprintln!("Remote client {addr}: {err}");
+            }
+        });
+    }
+}
+
+async fn handle_client(
+    app_state: Arc<Mutex<AppState>>,
+    acceptor: TlsAcceptor,
+    tokens: &[Token],
+    stream: TcpStream,
+    addr: SocketAddr,
+) -> Result<(), Box<dyn Error>> {
+    // Only the client's part is timed, a command like `restart` may well take longer
+    let mut stream = timeout(CLIENT_TIMEOUT, acceptor.accept(stream))
+        .await
+        .map_err(|_| "the TLS handshake didn't finish in time")??;
+    let message = match socket::read_message(&mut stream).await {
+        Ok(message) => message,
+        Err(err) => {
+            let answer = Response::ErrorData(format!("Can't read the command, {err}")).to_string();
+            stream.write_all(format!("{}\n", answer).as_bytes()).await?;
+            stream.shutdown().await?;
+            return Err(err.into());
+        }
+    };
+
+    let answer = match message
+        .split_once('\n')
+        .and_then(|(auth, msg)| Some((authenticate(tokens, auth)?, msg)))
+    {
+        Some((token, msg)) => {
+            let peer = Peer::Remote {
+                addr,
+                token: token.name.clone(),
+                scope: token.scope,
+            };
+            socket::answer(app_state, &peer, msg).await
+        }
+        None => {
+            eprintln!("Remote client {addr} sent no valid token");
+            Response::Error("Invalid token").to_string()
+        }
+    };
+    stream.write_all(format!("{}\n", answer).as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// The tokens long enough to be used, an error if that leaves none.
+pub fn usable_tokens(tokens: Vec<Token>) -> Result<Vec<Token>, Box<dyn Error>> {
+    let tokens: Vec<Token> = tokens
+        .into_iter()
+        .filter(|token| {
+            let long_enough = token.token.len() >= MIN_TOKEN_LENGTH;
+            if !long_enough {
+                eprintln!(
+                    "Token {} is shorter than {MIN_TOKEN_LENGTH} characters, ignoring it",
+                    token.name
+                );
+            }
+            long_enough
+        })
+        .collect();
+    if tokens.is_empty() {
+        return Err("no usable tokens, nobody could connect".into());
+    }
+    Ok(tokens)
+}
+
+/// The token of a `Bearer <token>` line, compared in constant time.
+pub fn authenticate<'a>(tokens: &'a [Token], line: &str) -> Option<&'a Token> {
+    let sent = line.trim().strip_prefix("Bearer ")?.trim().as_bytes();
+    tokens.iter().find(|token| {
+        let token = token.token.as_bytes();
+        token.len() == sent.len()
+            && token
+                .iter()
+                .zip(sent)
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(name: &str, token: &str) -> Token {
+        Token {
+            name: name.to_string(),
+            token: token.to_string(),
+            scope: Scope::Read,
+        }
+    }
+
+    #[test]
+    fn authenticates_bearer_tokens() {
+        let tokens = [
+            token("ci", "0123456789abcdef"),
+            token("ops", "fedcba9876543210"),
+        ];
+        let name = |line| authenticate(&tokens, line).map(|token| token.name.as_str());
+        assert_eq!(name("Bearer 0123456789abcdef"), Some("ci"));
+        assert_eq!(name("Bearer fedcba9876543210\r"), Some("ops"));
+        assert_eq!(name("Bearer 0123456789abcdeg"), None);
+        assert_eq!(name("Bearer 0123456789abcde"), None);
+        assert_eq!(name("Bearer 0123456789abcdef0"), None);
+        assert_eq!(name("Bearer "), None);
+        assert_eq!(name("0123456789abcdef"), None);
+        assert_eq!(name("Basic 0123456789abcdef"), None);
+    }
+
+    #[test]
+    fn ignores_short_tokens() {
+        let tokens = usable_tokens(vec![
+            token("short", "0123456789"),
+            token("long", "0123456789abcdef"),
+        ])
+        .unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].name, "long");
+        assert!(authenticate(&tokens, "Bearer 0123456789").is_none());
+
+        assert!(usable_tokens(vec![token("short", "0123456789")]).is_err());
+        assert!(usable_tokens(vec![]).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+use std::{sync::Arc, time::Duration};
+
+use nix::{
+    sys::signal::{Signal, kill},
+    unistd::Pid,
+};
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    sync::Mutex,
+};
+
+use crate::{
+    AppState, backup, cgroups::Cgroup, children, config::Config, dependencies, processes::Process,
+    socket,
+};
+
+/// How long the kill after `shutdown_timeout` waits for the state of a process.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub async fn wait_for_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Can't handle SIGTERM");
+    let mut interrupt = signal(SignalKind::interrupt()).expect("Can't handle SIGINT");
+    tokio::select! {
+        _ = terminate.recv() => println!("Got SIGTERM, shutting down..."),
+        _ = interrupt.recv() => println!("Got SIGINT, shutting down..."),
+    }
+}
+
+/// Saves the processes as they are, then stops them dependents first and otherwise last-started
+/// first, each one getting its own `stop_timeout`. Whatever is still alive when
+/// `shutdown_timeout` runs out is killed.
+pub async fn shutdown(app_state: Arc<Mutex<AppState>>, config: &Config) {
+    let (mut processes, max_id) = {
+        let app_state_guard = app_state.lock().await;
+        let processes: Vec<Arc<Process>> = app_state_guard.processes.values().cloned().collect();
+        (processes, app_state_guard.id)
+    };
+    processes.sort_by_key(|process| process.id);
+    let order = dependencies::order(&processes).unwrap_or_default();
+
+    // Saved before stopping, otherwise every process would come back stopped
+    if let Err(err) = backup::write_backup(max_id, processes.clone()).await {
+        eprintln!("Can't save processes: {err}");
+    }
+
+    let stop_all = async {
+        let mut stopping = processes.clone();
+        stopping.sort_by_key(|process| {
+            let position = order
+                .iter()
+                .position(|title| *title == process.ecosystem.title);
+            (position, process.id)
+        });
+        for process in stopping.iter().rev() {
+            process.stop().await;
+            children::forget(process.id).await;
+        }
+    };
+    let deadline = Duration::from_secs(config.shutdown_timeout);
+    if tokio::time::timeout(deadline, stop_all).await.is_err() {
+        eprintln!(
+            "Processes didn't stop in {}s, killing...",
+            config.shutdown_timeout
+        );
+        for process in &processes {
+            // A stop still under way only holds the state for a moment
+            if let Ok(state) = tokio::time::timeout(LOCK_TIMEOUT, process.state.lock()).await {
+                for pid in state.uid.iter().chain(state.descendants.keys()) {
+                    let _ = kill(Pid::from_raw(*pid as i32), Signal::SIGKILL);
+                }
+            }
+            // Whatever got away from the descendant tracking is still in the cgroup
+            if let Some(cgroup) = Cgroup::of(process.id).filter(|cgroup| !cgroup.is_empty()) {
+                cgroup.kill();
+            }
+            children::forget(process.id).await;
+        }
+    }
+
+    socket::remove_socket();
+}
@@ -1,18 +1,42 @@
-use std::{borrow::Cow, error::Error, fs, path::Path, sync::Arc};
+use nix::unistd::geteuid;
+use std::{
+    borrow::Cow,
+    env,
+    error::Error,
+    fs, io,
+    os::{
+        fd::{AsRawFd, FromRawFd, RawFd},
+        unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
     net::{UnixListener, UnixStream},
     sync::Mutex,
+    time::timeout,
 };
 
-use crate::commands;
+use crate::{
+    access::{self, Peer},
+    audit, commands,
+    config::Config,
+    upgrade,
+};
 
-const SOCKET_PATH: &str = "/tmp/fpm.sock";
+const SOCKET_NAME: &str = "fpm.sock";
+/// A message is a command with its arguments, nothing needs more than this.
+pub const MAX_MESSAGE: u64 = 64 * 1024;
+/// How long a client gets to send its message, so idle connections don't pile up.
+pub const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub enum Response {
     Successfully(Option<&'static str>),
     Data(String),
     Error(&'static str),
+    ErrorData(String),
 }
 
 impl Response {
@@ -24,18 +48,101 @@ impl Response {
             Self::Error(err_code) => {
                 format!("error {}", err_code.to_string())
             }
+            Self::ErrorData(err) => format!("error {}", err),
             Self::Successfully(message) => format!("success {}", message.unwrap_or("")),
         }
     }
 }
 
-pub async fn start_socket(app_state: Arc<Mutex<crate::AppState>>) -> Result<(), Box<dyn Error>> {
-    if Path::new(SOCKET_PATH).exists() {
-        fs::remove_file(SOCKET_PATH)?;
+static LISTENER_FD: OnceLock<RawFd> = OnceLock::new();
+static SOCKET_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// `socket` from the config, otherwise `$XDG_RUNTIME_DIR/fpm.sock`, otherwise `fpm.sock` in
+/// a directory of the daemon's own. The CLI looks in the same places.
+fn socket_path(config: &Config) -> PathBuf {
+    if let Some(path) = &config.socket {
+        return path.clone();
+    }
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join(SOCKET_NAME),
+        _ => fallback_dir().join(SOCKET_NAME),
+    }
+}
+
+/// Where the socket goes without `$XDG_RUNTIME_DIR`, like for a system unit.
+fn fallback_dir() -> PathBuf {
+    let euid = geteuid();
+    if euid.is_root() {
+        PathBuf::from("/run/fpm")
+    } else {
+        PathBuf::from(format!("/tmp/fpm-{euid}"))
+    }
+}
+
+/// Creates `dir` for the socket, or makes sure the one that is there is ours. Anyone can
+/// create names in /tmp, and a socket in someone else's directory could be swapped out.
+fn own_dir(dir: &Path) -> io::Result<()> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err),
+        _ => {}
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != geteuid().as_raw() || metadata.mode() & 0o022 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} isn't a directory only fpmd's user can write to, set socket in the config",
+                dir.display()
+            ),
+        ));
     }
+    // Other users only pass through to a shared socket
+    let mode = if access::is_shared() { 0o711 } else { 0o700 };
+    fs::set_permissions(dir, fs::Permissions::from_mode(mode))
+}
+
+/// The fd of the listening socket, handed over to the new binary on upgrade.
+pub fn listener_fd() -> Option<RawFd> {
+    LISTENER_FD.get().copied()
+}
 
-    let listener = UnixListener::bind(SOCKET_PATH)?;
-    println!("Демон слушает на {}...", SOCKET_PATH);
+pub fn remove_socket() {
+    if let Some(path) = SOCKET_PATH.get()
+        && path.exists()
+    {
+        let _ = fs::remove_file(path);
+    }
+}
+
+pub async fn start_socket(
+    app_state: Arc<Mutex<crate::AppState>>,
+    config: &Config,
+    inherited: Option<RawFd>,
+) -> Result<(), Box<dyn Error>> {
+    let path = SOCKET_PATH.get_or_init(|| socket_path(config));
+    let listener = if let Some(fd) = inherited {
+        // SAFETY: the fd was passed to us by the previous daemon right before it exec'd us
+        let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+        upgrade::adopt_listener(fd)?;
+        listener.set_nonblocking(true)?;
+        UnixListener::from_std(listener)?
+    } else {
+        match path.parent() {
+            Some(dir) if dir == fallback_dir() => own_dir(dir)?,
+            Some(dir) => fs::create_dir_all(dir)?,
+            None => {}
+        }
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        // Who may do what is decided per command, the socket only keeps out everyone else
+        let mode = if access::is_shared() { 0o666 } else { 0o600 };
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        listener
+    };
+    let _ = LISTENER_FD.set(listener.as_raw_fd());
+    println!("Демон слушает на {}...", path.display());
 
     loop {
         let app_state_clone = app_state.clone();
@@ -50,43 +157,100 @@ async fn handle_connection(
     app_state: Arc<Mutex<crate::AppState>>,
     mut stream: UnixStream,
 ) -> Result<(), Box<dyn Error>> {
-    let mut buf = [0; 1024];
-
-    match stream.read(&mut buf).await {
-        Ok(n) if n > 0 => {
-            let msg = String::from_utf8_lossy(&buf[..n]);
-            println!("Get: {}", msg);
-            let answer: Response = match_command(app_state, msg.to_string()).await;
-            if let Err(e) = stream
-                .write_all(format!("{}\n", answer.to_string()).as_bytes())
-                .await
-            {
+    let peer = Peer::Local(stream.peer_cred()?);
+
+    match read_message(&mut stream).await {
+        Ok(msg) if !msg.is_empty() => {
+            let answer = answer(app_state, &peer, &msg).await;
+            if let Err(e) = stream.write_all(format!("{}\n", answer).as_bytes()).await {
                 eprintln!("Error sending: {}", e);
             }
         }
         Ok(_) => println!("Empty"),
-        Err(e) => eprintln!("Error reading: {}", e),
+        Err(e) => {
+            eprintln!("Error reading from {}: {}", peer, e);
+            let answer = Response::ErrorData(format!("Can't read the command, {e}"));
+            let _ = stream
+                .write_all(format!("{}\n", answer.to_string()).as_bytes())
+                .await;
+        }
     }
     Ok(())
 }
 
-async fn match_command(app_state: Arc<Mutex<crate::AppState>>, command: String) -> Response {
-    let mut command: Vec<&str> = command.split_whitespace().collect();
+/// Reads a whole message, the client closes its side once it is sent. Longer messages than
+/// `MAX_MESSAGE` are refused rather than cut short.
+pub async fn read_message<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<String> {
+    let mut buf = vec![];
+    let mut message = stream.take(MAX_MESSAGE + 1);
+    match timeout(CLIENT_TIMEOUT, message.read_to_end(&mut buf)).await {
+        Ok(read) => read?,
+        Err(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the message didn't arrive in time",
+            ));
+        }
+    };
+    if buf.len() as u64 > MAX_MESSAGE {
+        // Closing with unread data would reset the connection before the client gets the error
+        let _ = timeout(
+            CLIENT_TIMEOUT,
+            tokio::io::copy(message.into_inner(), &mut tokio::io::sink()),
+        )
+        .await;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the message is longer than {MAX_MESSAGE} bytes"),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&buf).to_string())
+}
 
-    if command.is_empty() {
+/// Runs a message from either listener and records it in the audit log. Returns what goes
+/// back to the client.
+pub async fn answer(app_state: Arc<Mutex<crate::AppState>>, peer: &Peer, msg: &str) -> String {
+    println!("Get from {}: {}", peer, msg);
+    let answer = match_command(app_state, peer, msg.to_string())
+        .await
+        .to_string();
+    audit::record(peer, msg, &answer).await;
+    answer
+}
+
+async fn match_command(
+    app_state: Arc<Mutex<crate::AppState>>,
+    peer: &Peer,
+    command: String,
+) -> Response {
+    // The argument is passed on as sent, `run` carries JSON where whitespace matters
+    let (command_name, arg) = match command.trim().split_once(char::is_whitespace) {
+        Some((command_name, arg)) => (command_name, arg.trim().to_string()),
+        None => (command.trim(), String::new()),
+    };
+
+    if command_name.is_empty() {
         return Response::Error("The command was not found");
     }
-
-    let command_name = command.remove(0);
-    let arg = command.join(" ");
+    if !access::allowed(peer, command_name) {
+        return Response::ErrorData(format!("{} isn't allowed to {}", peer, command_name));
+    }
 
     let result: Result<Response, Box<dyn Error>> = match command_name {
         "start" => commands::start(app_state, arg).await,
         "stop" => commands::stop(app_state, arg).await,
         "restart" => commands::restart(app_state, arg).await,
+        "reload" => commands::reload(app_state, arg).await,
         "status" => commands::status(app_state, arg).await,
+        "describe" => commands::describe(app_state, arg).await,
+        "scale" => commands::scale(app_state, arg).await,
+        "run" => commands::run(app_state, arg).await,
         "backup" => commands::backup(app_state, arg).await,
         "delete" => commands::delete(app_state, arg).await,
+        "export" => commands::export(app_state, arg).await,
+        "import" => commands::import(app_state, arg).await,
+        "upgrade" => commands::upgrade(app_state, arg).await,
+        "audit" => commands::audit(app_state, arg).await,
         _ => Ok(Response::Error("Unknown command")),
     };
 
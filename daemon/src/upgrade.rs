@@ -0,0 +1,121 @@
+use std::{
+    env,
+    error::Error,
+    os::{
+        fd::{BorrowedFd, RawFd},
+        unix::process::CommandExt,
+    },
+    path::PathBuf,
+    sync::Arc,
+};
+
+use nix::fcntl::{FcntlArg, FdFlag, fcntl};
+use tokio::{fs, sync::Mutex};
+
+use crate::{
+    AppState,
+    backup::{Backup, BackupProcess},
+    processes::Process,
+    project_dir, socket,
+};
+
+const LISTEN_FD_ARG: &str = "--listen-fd";
+const HANDOFF_ARG: &str = "--handoff";
+
+/// What the previous daemon passed across `exec` when it upgraded itself.
+#[derive(Debug, Default)]
+pub struct Inherited {
+    pub listener: Option<RawFd>,
+    pub handoff: Option<PathBuf>,
+}
+
+impl Inherited {
+    pub fn from_args() -> Self {
+        let mut inherited = Self::default();
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                LISTEN_FD_ARG => inherited.listener = args.next().and_then(|fd| fd.parse().ok()),
+                HANDOFF_ARG => inherited.handoff = args.next().map(PathBuf::from),
+                _ => eprintln!("Unknown argument: {arg}"),
+            }
+        }
+        inherited
+    }
+}
+
+/// Processes the previous daemon was managing, including the ones never backed up.
+pub async fn load_handoff(path: PathBuf) -> Option<Vec<BackupProcess>> {
+    let content = fs::read_to_string(&path).await.ok()?;
+    let _ = fs::remove_file(&path).await;
+    match toml::from_str::<Backup>(&content) {
+        Ok(backup) => Some(backup.process),
+        Err(err) => {
+            eprintln!("Can't read the handoff of the previous daemon: {err}");
+            None
+        }
+    }
+}
+
+/// The binary to re-exec. When it has been replaced on disk, `/proc/self/exe` points at the
+/// deleted inode, so the path is taken from the link text instead.
+fn current_binary() -> std::io::Result<PathBuf> {
+    let exe = std::fs::read_link("/proc/self/exe")?;
+    let exe = exe.to_string_lossy();
+    Ok(PathBuf::from(
+        exe.strip_suffix(" (deleted)").unwrap_or(&exe),
+    ))
+}
+
+fn set_cloexec(fd: RawFd, cloexec: bool) -> nix::Result<()> {
+    let flags = if cloexec {
+        FdFlag::FD_CLOEXEC
+    } else {
+        FdFlag::empty()
+    };
+    // SAFETY: the listener fd stays open for the whole lifetime of the daemon
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    fcntl(fd, FcntlArg::F_SETFD(flags)).map(|_| ())
+}
+
+/// Replaces the daemon with the binary now on disk. Children are not touched: their pids are
+/// already recorded and the new daemon adopts them, while the listening socket is inherited so
+/// clients never see it disappear. Only returns if the upgrade failed.
+pub async fn exec_upgrade(app_state: Arc<Mutex<AppState>>) -> Result<(), Box<dyn Error>> {
+    let binary = current_binary()?;
+    if !binary.is_file() {
+        return Err(format!("{} does not exist", binary.display()).into());
+    }
+    let listener = socket::listener_fd().ok_or("the socket is not listening")?;
+    let project_dir = project_dir().ok_or("can't get ProjectDir")?;
+    let handoff = project_dir.data_dir().join("handoff.toml");
+
+    let (processes, max_id) = {
+        let app_state_guard = app_state.lock().await;
+        (app_state_guard.processes.clone(), app_state_guard.id)
+    };
+    let processes: Vec<Arc<Process>> = processes.values().cloned().collect();
+    fs::write(
+        &handoff,
+        Backup::from_processes(max_id, processes).await.to_toml()?,
+    )
+    .await?;
+
+    set_cloexec(listener, false)?;
+    println!("Upgrading to {}...", binary.display());
+    let err = std::process::Command::new(&binary)
+        .arg(LISTEN_FD_ARG)
+        .arg(listener.to_string())
+        .arg(HANDOFF_ARG)
+        .arg(&handoff)
+        .exec();
+
+    let _ = set_cloexec(listener, true);
+    let _ = fs::remove_file(&handoff).await;
+    Err(Box::new(err))
+}
+
+/// Makes sure an inherited listener isn't leaked into the children we spawn.
+pub fn adopt_listener(fd: RawFd) -> nix::Result<()> {
+    set_cloexec(fd, true)
+}
@@ -11,6 +11,9 @@ BASE_URL="https://github.com/feproldo/process-manager/releases/download/v$VERSIO
 curl -L "$BASE_URL/fpm" -o "$BINDIR/fpm"
 curl -L "$BASE_URL/fpmd" -o "$BINDIR/fpmd"
 curl -L "$BASE_URL/fpmd.service" -o "$SYSTEMD_DIR/fpmd.service"
+# Hands the unit's cgroup to fpmd, cgroup limits are off without it
+mkdir -p "$SYSTEMD_DIR/fpmd.service.d"
+printf '[Service]\nDelegate=yes\n' > "$SYSTEMD_DIR/fpmd.service.d/delegate.conf"
 
 chmod +x "$BINDIR/fpm" "$BINDIR/fpmd"
 